@@ -1,28 +1,353 @@
-use syn::{parse_str, punctuated::Punctuated, Attribute, Item, Meta, Path, Token};
+use proc_macro2::TokenStream;
+use quote::ToTokens;
+use std::cell::RefCell;
+use syn::{
+    parse::{ParseStream, Parser},
+    parse_str,
+    punctuated::Punctuated,
+    visit::{self, Visit},
+    visit_mut::{self, VisitMut},
+    Attribute, Error, Expr, ExprLit, File, ForeignItem, Ident, ImplItem, Item, ItemMod, Lit, Local,
+    Meta, MetaNameValue, Path, StmtMacro, Token, TraitItem,
+};
 
 thread_local! {
     static RUST_MINIFY_SKIP: Path = parse_str::<Path>("rust_minify::skip").unwrap();
+    static RUST_MINIFY_MINIFY: Path = parse_str::<Path>("rust_minify::minify").unwrap();
+    static RUST_MINIFY_OPTIONS: Path = parse_str::<Path>("rust_minify::options").unwrap();
+    /// Paths registered by [`with_skip_paths`] which work like `rust_minify::skip`.
+    static SKIP_PATHS: RefCell<Vec<Path>> = const { RefCell::new(Vec::new()) };
 }
 
-fn is_minify_skip_meta(meta: &Meta) -> bool {
+/// Calls `f` with `paths` also recognized as the skip attribute on this thread.
+pub fn with_skip_paths<R>(paths: &[Path], f: impl FnOnce() -> R) -> R {
+    struct Restore(usize);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SKIP_PATHS.with(|p| p.borrow_mut().truncate(self.0));
+        }
+    }
+    let _restore = Restore(SKIP_PATHS.with(|p| {
+        let mut p = p.borrow_mut();
+        let len = p.len();
+        p.extend_from_slice(paths);
+        len
+    }));
+    f()
+}
+
+fn is_skip_path(path: &Path) -> bool {
+    RUST_MINIFY_SKIP.with(|p| p == path) || SKIP_PATHS.with(|p| p.borrow().contains(path))
+}
+
+/// Returns `true` if `meta` is a path satisfying `f`, also inside `cfg_attr`.
+fn is_path_meta(meta: &Meta, f: &impl Fn(&Path) -> bool) -> bool {
     match meta {
-        Meta::Path(path) => RUST_MINIFY_SKIP.with(|p| p == path),
+        Meta::Path(path) => f(path),
         Meta::List(list) if list.path.is_ident("cfg_attr") => list
             .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
-            .map(|punct| punct.iter().skip(1).any(is_minify_skip_meta))
+            .map(|punct| punct.iter().skip(1).any(|meta| is_path_meta(meta, f)))
             .unwrap_or_default(),
         _ => false,
     }
 }
 
+fn is_minify_skip_meta(meta: &Meta) -> bool {
+    is_path_meta(meta, &is_skip_path)
+}
+
+fn is_minify_marker_meta(meta: &Meta) -> bool {
+    is_path_meta(meta, &|path| RUST_MINIFY_MINIFY.with(|p| p == path))
+}
+
 pub fn is_minify_skip(attrs: &[Attribute]) -> bool {
     attrs.iter().any(|attr| is_minify_skip_meta(&attr.meta))
 }
 
+pub fn find_minify_skip(attrs: &[Attribute]) -> Option<&Attribute> {
+    attrs.iter().find(|attr| is_minify_skip_meta(&attr.meta))
+}
+
 pub fn drain_minify_skip(attrs: &mut Vec<Attribute>) -> bool {
     any_drain_filter(attrs, |attr| is_minify_skip_meta(&attr.meta))
 }
 
+/// Returns `true` for `#[rust_minify::skip]`, `#[rust_minify::options(..)]` and, if `marker` is
+/// `true`, `#[rust_minify::minify]`, which are removed with `remove_skip`.
+pub fn is_control_meta(meta: &Meta, marker: bool) -> bool {
+    is_minify_skip_meta(meta)
+        || RUST_MINIFY_OPTIONS.with(|p| p == meta.path())
+        || (marker && is_minify_marker_meta(meta))
+}
+
+/// Returns `true` if `attrs` has `#[rust_minify::minify]`, which marks items to minify in the
+/// opt-in mode.
+pub fn is_minify_marked(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| is_minify_marker_meta(&attr.meta))
+}
+
+pub fn drain_minify_marker(attrs: &mut Vec<Attribute>) -> bool {
+    any_drain_filter(attrs, |attr| is_minify_marker_meta(&attr.meta))
+}
+
+/// Names of the [`MinifyOption`](crate::MinifyOption) fields which
+/// `#[rust_minify::options(..)]` can override for the item and the items in it.
+pub const SCOPED_OPTIONS: [&str; 4] = [
+    "add_rustfmt_skip",
+    "rename_items",
+    "rename_locals",
+    "shorten_generics",
+];
+
+fn is_minify_options(attr: &Attribute) -> bool {
+    RUST_MINIFY_OPTIONS.with(|p| p == attr.path())
+}
+
+/// Parses `#[rust_minify::options(name, name = true, name = false)]`, where `name` means
+/// `name = true`.
+fn parse_minify_options(attr: &Attribute) -> syn::Result<Vec<(Ident, bool)>> {
+    let metas = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+    metas
+        .into_iter()
+        .map(|meta| {
+            let (path, value) = match &meta {
+                Meta::Path(path) => (path, true),
+                Meta::NameValue(MetaNameValue {
+                    path,
+                    value:
+                        Expr::Lit(ExprLit {
+                            lit: Lit::Bool(value),
+                            ..
+                        }),
+                    ..
+                }) => (path, value.value),
+                _ => return Err(Error::new_spanned(meta, "expected `name` or `name = bool`")),
+            };
+            match path.get_ident() {
+                Some(ident) if SCOPED_OPTIONS.contains(&ident.to_string().as_str()) => {
+                    Ok((ident.clone(), value))
+                }
+                _ => Err(Error::new_spanned(
+                    path,
+                    format!(
+                        "unsupported option `{}`, expected one of `{}` or `{}`",
+                        path.to_token_stream().to_string().replace(' ', ""),
+                        SCOPED_OPTIONS[..SCOPED_OPTIONS.len() - 1].join("`, `"),
+                        SCOPED_OPTIONS[SCOPED_OPTIONS.len() - 1],
+                    ),
+                )),
+            }
+        })
+        .collect()
+}
+
+/// Returns the value of the option `name` set by `#[rust_minify::options(..)]` in `attrs`.
+pub fn scoped_option(attrs: &[Attribute], name: &str) -> Option<bool> {
+    attrs
+        .iter()
+        .filter(|attr| is_minify_options(attr))
+        .flat_map(|attr| parse_minify_options(attr).unwrap_or_default())
+        .filter(|(ident, _)| ident == name)
+        .map(|(_, value)| value)
+        .last()
+}
+
+/// Returns `true` if `file` has `#[rust_minify::options(..)]`, or the first malformed one.
+pub fn has_scoped_options(file: &File) -> syn::Result<bool> {
+    #[derive(Default)]
+    struct Check {
+        found: bool,
+        error: Option<Error>,
+    }
+    impl<'ast> Visit<'ast> for Check {
+        fn visit_attribute(&mut self, node: &'ast Attribute) {
+            if is_minify_options(node) {
+                self.found = true;
+                if let Err(err) = parse_minify_options(node) {
+                    self.error.get_or_insert(err);
+                }
+            }
+        }
+    }
+    let mut check = Check::default();
+    check.visit_file(file);
+    match check.error {
+        Some(err) => Err(err),
+        None => Ok(check.found),
+    }
+}
+
+/// Parses the outer attributes at the start of `tokens`, which syn keeps in `Item::Verbatim`.
+pub fn verbatim_attrs(tokens: &TokenStream) -> Vec<Attribute> {
+    let parser = |input: ParseStream| {
+        let attrs = input.call(Attribute::parse_outer)?;
+        input.parse::<TokenStream>()?;
+        Ok(attrs)
+    };
+    parser.parse2(tokens.clone()).unwrap_or_default()
+}
+
+/// Returns `true` if `item` has the skip attribute, also in `Item::Verbatim`.
+pub fn is_item_skipped(item: &Item) -> bool {
+    match item {
+        Item::Verbatim(tokens) => is_minify_skip(&verbatim_attrs(tokens)),
+        _ => item.get_attributes().is_some_and(is_minify_skip),
+    }
+}
+
+fn is_item_marked(item: &Item) -> bool {
+    match item {
+        Item::Verbatim(tokens) => is_minify_marked(&verbatim_attrs(tokens)),
+        _ => item.get_attributes().is_some_and(is_minify_marked),
+    }
+}
+
+/// Returns `true` if an item in the inline module `item` has `#[rust_minify::minify]`.
+fn contains_marked(item: &Item) -> bool {
+    match item {
+        Item::Mod(ItemMod {
+            content: Some((_, items)),
+            ..
+        }) => items
+            .iter()
+            .any(|item| is_item_marked(item) || contains_marked(item)),
+        _ => false,
+    }
+}
+
+/// Replaces the items emitted as they are in the opt-in mode, which are not marked by
+/// `#[rust_minify::minify]` themselves or by an enclosing module, by `Item::Verbatim` of their
+/// tokens, so that no pass changes them, and collects the tokens.
+///
+/// Unmarked inline modules with marked items are descended instead.
+pub fn take_unmarked_items(items: &mut [Item], unmarked: &mut Vec<TokenStream>) {
+    for item in items {
+        if is_item_marked(item) {
+            continue;
+        }
+        match item {
+            Item::Mod(ItemMod {
+                content: Some((_, items)),
+                ..
+            }) if items
+                .iter()
+                .any(|item| is_item_marked(item) || contains_marked(item)) =>
+            {
+                take_unmarked_items(items, unmarked)
+            }
+            _ => {
+                let tokens = item.to_token_stream();
+                unmarked.push(tokens.clone());
+                *item = Item::Verbatim(tokens);
+            }
+        }
+    }
+}
+
+/// Removes `#[rust_minify::options(..)]` and, if `marker` is `true`, `#[rust_minify::minify]`
+/// from every item.
+pub struct DrainControlAttrs {
+    pub marker: bool,
+}
+
+impl DrainControlAttrs {
+    pub fn drain(&self, attrs: &mut Vec<Attribute>) {
+        any_drain_filter(attrs, is_minify_options);
+        if self.marker {
+            drain_minify_marker(attrs);
+        }
+    }
+}
+
+impl VisitMut for DrainControlAttrs {
+    fn visit_item_mut(&mut self, node: &mut Item) {
+        if let Some(attrs) = node.get_attributes_mut() {
+            self.drain(attrs);
+        }
+        visit_mut::visit_item_mut(self, node);
+    }
+}
+
+/// Calls `f` with the tokens and the skip attribute of every node with the skip attribute,
+/// without descending into them.
+///
+/// Items, impl, trait and foreign items, `let` and macro statements and expressions are checked.
+pub struct SkippedNodes<F>(pub F);
+
+impl<F: FnMut(TokenStream, &Attribute)> SkippedNodes<F> {
+    fn skip(&mut self, node: &impl ToTokens, attrs: &[Attribute]) -> bool {
+        match find_minify_skip(attrs) {
+            Some(attr) => {
+                (self.0)(node.to_token_stream(), attr);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<'ast, F: FnMut(TokenStream, &Attribute)> Visit<'ast> for SkippedNodes<F> {
+    fn visit_expr(&mut self, node: &'ast Expr) {
+        if !node
+            .get_attributes()
+            .is_some_and(|attrs| self.skip(node, attrs))
+        {
+            visit::visit_expr(self, node);
+        }
+    }
+
+    fn visit_foreign_item(&mut self, node: &'ast ForeignItem) {
+        if !node
+            .get_attributes()
+            .is_some_and(|attrs| self.skip(node, attrs))
+        {
+            visit::visit_foreign_item(self, node);
+        }
+    }
+
+    fn visit_impl_item(&mut self, node: &'ast ImplItem) {
+        if !node
+            .get_attributes()
+            .is_some_and(|attrs| self.skip(node, attrs))
+        {
+            visit::visit_impl_item(self, node);
+        }
+    }
+
+    fn visit_item(&mut self, node: &'ast Item) {
+        let skipped = match node {
+            Item::Verbatim(tokens) => self.skip(node, &verbatim_attrs(tokens)),
+            _ => node
+                .get_attributes()
+                .is_some_and(|attrs| self.skip(node, attrs)),
+        };
+        if !skipped {
+            visit::visit_item(self, node);
+        }
+    }
+
+    fn visit_local(&mut self, node: &'ast Local) {
+        if !self.skip(node, &node.attrs) {
+            visit::visit_local(self, node);
+        }
+    }
+
+    fn visit_stmt_macro(&mut self, node: &'ast StmtMacro) {
+        if !self.skip(node, &node.attrs) {
+            visit::visit_stmt_macro(self, node);
+        }
+    }
+
+    fn visit_trait_item(&mut self, node: &'ast TraitItem) {
+        if !node
+            .get_attributes()
+            .is_some_and(|attrs| self.skip(node, attrs))
+        {
+            visit::visit_trait_item(self, node);
+        }
+    }
+}
+
 pub trait ItemExt {
     fn get_attributes(&self) -> Option<&[Attribute]>;
     fn get_attributes_mut(&mut self) -> Option<&mut Vec<Attribute>>;
@@ -72,6 +397,86 @@ impl ItemExt for Item {
     }
 }
 
+macro_rules! impl_item_ext {
+    ($ty:ident { $($variant:ident),* $(,)? }) => {
+        impl ItemExt for $ty {
+            fn get_attributes(&self) -> Option<&[Attribute]> {
+                Some(match self {
+                    $($ty::$variant(it) => &it.attrs,)*
+                    _ => return None,
+                })
+            }
+
+            fn get_attributes_mut(&mut self) -> Option<&mut Vec<Attribute>> {
+                Some(match self {
+                    $($ty::$variant(it) => &mut it.attrs,)*
+                    _ => return None,
+                })
+            }
+        }
+    };
+}
+
+impl_item_ext!(ImplItem {
+    Const,
+    Fn,
+    Macro,
+    Type
+});
+impl_item_ext!(TraitItem {
+    Const,
+    Fn,
+    Macro,
+    Type
+});
+impl_item_ext!(ForeignItem {
+    Fn,
+    Macro,
+    Static,
+    Type
+});
+impl_item_ext!(Expr {
+    Array,
+    Assign,
+    Async,
+    Await,
+    Binary,
+    Block,
+    Break,
+    Call,
+    Cast,
+    Closure,
+    Const,
+    Continue,
+    Field,
+    ForLoop,
+    Group,
+    If,
+    Index,
+    Infer,
+    Let,
+    Lit,
+    Loop,
+    Macro,
+    Match,
+    MethodCall,
+    Paren,
+    Path,
+    Range,
+    RawAddr,
+    Reference,
+    Repeat,
+    Return,
+    Struct,
+    Try,
+    TryBlock,
+    Tuple,
+    Unary,
+    Unsafe,
+    While,
+    Yield
+});
+
 fn any_drain_filter<T, F>(v: &mut Vec<T>, mut filter: F) -> bool
 where
     F: FnMut(&T) -> bool,
@@ -108,6 +513,73 @@ mod tests {
         assert_eq!(is_minify_skip(attrs), expected);
     }
 
+    #[test_case("#[my_tool::verbatim]mod a;", true; "my_tool::verbatim")]
+    #[test_case("#[cfg_attr(foo, my_tool::verbatim)]mod a;", true; "cfg_attr(foo, my_tool::verbatim)")]
+    #[test_case("#[rust_minify::skip]mod a;", true; "rust_minify::skip")]
+    #[test_case("#[verbatim]mod a;", false; "verbatim")]
+    fn test_with_skip_paths(content: &str, expected: bool) {
+        let mut item = parse_str::<Item>(content).unwrap();
+        let paths = [parse_str::<Path>("my_tool::verbatim").unwrap()];
+        with_skip_paths(&paths, || {
+            assert_eq!(is_minify_skip(item.get_attributes().unwrap()), expected);
+            assert_eq!(
+                drain_minify_skip(item.get_attributes_mut().unwrap()),
+                expected
+            );
+        });
+        assert!(!is_minify_skip(item.get_attributes().unwrap()));
+    }
+
+    #[test]
+    fn test_take_unmarked_items() {
+        let mut file = syn::parse_file(
+            "#[rust_minify::minify] fn a() {} fn b() {} mod c { #[cfg_attr(x, rust_minify::minify)] fn d() {} fn e() {} } mod f { fn g() {} }",
+        )
+        .unwrap();
+        let mut unmarked = vec![];
+        take_unmarked_items(&mut file.items, &mut unmarked);
+        let unmarked: Vec<_> = unmarked.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            unmarked,
+            ["fn b () { }", "fn e () { }", "mod f { fn g () { } }"]
+        );
+        assert_eq!(
+            file.to_token_stream().to_string(),
+            "# [rust_minify :: minify] fn a () { } fn b () { } mod c { # [cfg_attr (x , rust_minify :: minify)] fn d () { } fn e () { } } mod f { fn g () { } }"
+        );
+        assert!(matches!(file.items[1], Item::Verbatim(_)));
+    }
+
+    #[test_case("#[rust_minify::options(rename_items = false)]mod a;", Some(false); "false")]
+    #[test_case("#[rust_minify::options(rename_locals, rename_items)]mod a;", Some(true); "bare")]
+    #[test_case("#[rust_minify::options(rename_locals)]mod a;", None; "other")]
+    #[test_case("#[rust_minify::options(unknown)]mod a;", None; "unknown")]
+    fn test_scoped_option(content: &str, expected: Option<bool>) {
+        let item = parse_str::<Item>(content).unwrap();
+        let attrs = item.get_attributes().unwrap();
+        assert_eq!(scoped_option(attrs, "rename_items"), expected);
+    }
+
+    #[test_case("#[rust_minify::options(rename_items = false)]mod a;", Ok(true); "valid")]
+    #[test_case("mod a;", Ok(false); "none")]
+    #[test_case("mod a { fn f() { #[rust_minify::options(unknown)] fn g() {} } }", Err(()); "unknown")]
+    #[test_case("#[rust_minify::options(rename_items = 1)]mod a;", Err(()); "not bool")]
+    fn test_has_scoped_options(content: &str, expected: Result<bool, ()>) {
+        let file = syn::parse_file(content).unwrap();
+        assert_eq!(has_scoped_options(&file).map_err(|_| ()), expected);
+    }
+
+    #[test_case("#[rust_minify::options(remove_unused)]mod a;", "remove_unused"; "file option")]
+    #[test_case("#[rust_minify::options(a::b = false)]mod a;", "a::b"; "path")]
+    fn test_parse_minify_options_unsupported(content: &str, name: &str) {
+        let item = parse_str::<Item>(content).unwrap();
+        let err = parse_minify_options(&item.get_attributes().unwrap()[0]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("unsupported option `{}`, expected one of `add_rustfmt_skip`, `rename_items`, `rename_locals` or `shorten_generics`", name)
+        );
+    }
+
     #[test]
     fn test_any_drain_filter() {
         let mut v = vec![1, 2, 3, 4, 5];
@@ -0,0 +1,617 @@
+#[path = "../cli.rs"]
+mod cli;
+
+use rust_minify::{bundle::Bundler, MinifyOption};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+use toml::{Table, Value};
+
+fn usage() -> String {
+    format!(
+        "\
+Usage: cargo minify [OPTIONS]
+
+Minify Cargo targets into `target/minify`, embedding out-of-line modules,
+the package library and path dependencies.
+
+Options:
+      --manifest-path <PATH>  Path to Cargo.toml
+      --bin <NAME>            Minify the binary NAME
+      --all-bins              Minify all binaries
+      --example <NAME>        Minify the example NAME
+      --all-examples          Minify all examples
+{}  -h, --help                  Print help
+  -V, --version               Print version
+
+Without target selection, all binaries are minified.
+Options are also read from `[package.metadata.rust_minify]` and
+`[workspace.metadata.rust_minify]`, command line options take precedence.
+",
+        cli::options_help()
+    )
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Args {
+    manifest_path: Option<PathBuf>,
+    bins: Vec<String>,
+    all_bins: bool,
+    examples: Vec<String>,
+    all_examples: bool,
+    /// Options given on the command line, applied over the manifest options.
+    flags: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Run(Args),
+    Help,
+    Version,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut parsed = Args::default();
+    let mut args = args.into_iter().peekable();
+    // `cargo minify` invokes `cargo-minify minify`
+    if args.peek().is_some_and(|arg| arg == "minify") {
+        args.next();
+    }
+    while let Some(arg) = args.next() {
+        let (flag, value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || match value.clone().or_else(|| args.next()) {
+            Some(value) => Ok(value),
+            None => Err(format!("`{}` requires a value", flag)),
+        };
+        match flag {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "--manifest-path" => parsed.manifest_path = Some(value()?.into()),
+            "--bin" => parsed.bins.push(value()?),
+            "--all-bins" => parsed.all_bins = true,
+            "--example" => parsed.examples.push(value()?),
+            "--all-examples" => parsed.all_examples = true,
+            _ => {
+                let mut flags = vec![arg.clone()];
+                let mut rest = args.by_ref().inspect(|arg| flags.push(arg.clone()));
+                if !cli::parse_option_arg(&mut MinifyOption::default(), &arg, &mut rest)? {
+                    return Err(format!("unexpected argument `{}`", arg));
+                }
+                parsed.flags.extend(flags);
+            }
+        }
+    }
+    Ok(Command::Run(parsed))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TargetKind {
+    Bin,
+    Example,
+}
+
+#[derive(Debug)]
+struct Target {
+    kind: TargetKind,
+    name: String,
+    path: PathBuf,
+}
+
+#[derive(Debug)]
+struct Package {
+    name: String,
+    targets: Vec<Target>,
+    /// Library crates embedded into each target, as `(name, root source file)`.
+    dependencies: Vec<(String, PathBuf)>,
+    edition: String,
+    metadata: Option<Table>,
+}
+
+fn read_manifest(path: &Path) -> Result<Table, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|err| format!("failed to read `{}`: {}", path.display(), err))?;
+    content
+        .parse::<Table>()
+        .map_err(|err| format!("failed to parse `{}`: {}", path.display(), err))
+}
+
+fn find_manifest(dir: &Path) -> Result<PathBuf, String> {
+    dir.ancestors()
+        .map(|dir| dir.join("Cargo.toml"))
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            format!(
+                "could not find `Cargo.toml` in `{}` or any parent directory",
+                dir.display()
+            )
+        })
+}
+
+/// Finds the root manifest of the workspace containing `manifest_path`.
+fn find_workspace_root(manifest_path: &Path) -> Result<(PathBuf, Table), String> {
+    let manifest = read_manifest(manifest_path)?;
+    if manifest.contains_key("workspace") {
+        return Ok((manifest_path.to_path_buf(), manifest));
+    }
+    for dir in manifest_path
+        .parent()
+        .into_iter()
+        .flat_map(Path::ancestors)
+        .skip(1)
+    {
+        let path = dir.join("Cargo.toml");
+        if path.is_file() {
+            let root = read_manifest(&path)?;
+            if root.contains_key("workspace") {
+                return Ok((path, root));
+            }
+        }
+    }
+    Ok((manifest_path.to_path_buf(), manifest))
+}
+
+fn get_table<'a>(table: &'a Table, keys: &[&str]) -> Option<&'a Table> {
+    keys.iter()
+        .try_fold(table, |table, key| table.get(*key)?.as_table())
+}
+
+/// Expands `members` of `[workspace]`, supporting `*` as the last path component.
+fn workspace_members(root: &Path, workspace: &Table) -> Result<Vec<PathBuf>, String> {
+    let mut members = vec![];
+    let globs = workspace.get("members").and_then(Value::as_array);
+    for member in globs.into_iter().flatten().filter_map(Value::as_str) {
+        match member.strip_suffix("/*") {
+            Some(parent) => {
+                let parent = root.join(parent);
+                let entries = fs::read_dir(&parent)
+                    .map_err(|err| format!("failed to read `{}`: {}", parent.display(), err))?;
+                let mut dirs: Vec<_> = entries
+                    .filter_map(|entry| Some(entry.ok()?.path()))
+                    .filter(|path| path.join("Cargo.toml").is_file())
+                    .collect();
+                dirs.sort();
+                members.extend(dirs);
+            }
+            None => members.push(root.join(member)),
+        }
+    }
+    Ok(members)
+}
+
+fn package_name(manifest: &Table) -> Option<&str> {
+    get_table(manifest, &["package"])?.get("name")?.as_str()
+}
+
+/// Returns the edition of the package, which may be inherited from `[workspace.package]`.
+fn package_edition<'a>(manifest: &'a Table, workspace: &'a Table) -> &'a str {
+    let edition = get_table(manifest, &["package"]).and_then(|package| package.get("edition"));
+    match edition {
+        Some(Value::String(edition)) => edition,
+        Some(Value::Table(edition)) if edition.get("workspace") == Some(&Value::Boolean(true)) => {
+            get_table(workspace, &["workspace", "package"])
+                .and_then(|package| package.get("edition")?.as_str())
+                .unwrap_or("2015")
+        }
+        _ => "2015",
+    }
+}
+
+/// Collects targets declared in `[[bin]]`/`[[example]]` and auto-discovered ones.
+fn collect_targets(
+    dir: &Path,
+    manifest: &Table,
+    kind: TargetKind,
+    targets: &mut Vec<Target>,
+) -> Result<(), String> {
+    let (section, auto, default_dir) = match kind {
+        TargetKind::Bin => ("bin", "autobins", "src/bin"),
+        TargetKind::Example => ("example", "autoexamples", "examples"),
+    };
+    let declared = manifest.get(section).and_then(Value::as_array);
+    for target in declared.into_iter().flatten().filter_map(Value::as_table) {
+        let Some(name) = target.get("name").and_then(Value::as_str) else {
+            return Err(format!(
+                "`[[{}]]` in `{}` has no name",
+                section,
+                dir.display()
+            ));
+        };
+        let path = match target.get("path").and_then(Value::as_str) {
+            Some(path) => dir.join(path),
+            None if kind == TargetKind::Bin && Some(name) == package_name(manifest) => {
+                dir.join("src/main.rs")
+            }
+            None => dir.join(default_dir).join(format!("{}.rs", name)),
+        };
+        targets.push(Target {
+            kind,
+            name: name.to_string(),
+            path,
+        });
+    }
+    let autodiscover = get_table(manifest, &["package"])
+        .and_then(|package| package.get(auto))
+        .and_then(Value::as_bool)
+        .unwrap_or(true);
+    if !autodiscover {
+        return Ok(());
+    }
+    let mut discovered = vec![];
+    if kind == TargetKind::Bin {
+        if let Some(name) = package_name(manifest) {
+            discovered.push((name.to_string(), dir.join("src/main.rs")));
+        }
+    }
+    if let Ok(entries) = fs::read_dir(dir.join(default_dir)) {
+        for path in entries.filter_map(|entry| Some(entry.ok()?.path())) {
+            let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            if path.is_dir() {
+                discovered.push((stem.to_string(), path.join("main.rs")));
+            } else if path.extension().is_some_and(|ext| ext == "rs") {
+                discovered.push((stem.to_string(), path));
+            }
+        }
+    }
+    discovered.sort();
+    for (name, path) in discovered {
+        let exists = targets
+            .iter()
+            .any(|target| target.kind == kind && (target.name == name || target.path == path));
+        if path.is_file() && !exists {
+            targets.push(Target { kind, name, path });
+        }
+    }
+    Ok(())
+}
+
+fn lib_path(dir: &Path, manifest: &Table) -> Option<PathBuf> {
+    let path = get_table(manifest, &["lib"])
+        .and_then(|lib| lib.get("path"))
+        .and_then(Value::as_str)
+        .map_or_else(|| dir.join("src/lib.rs"), |path| dir.join(path));
+    path.is_file().then_some(path)
+}
+
+/// Collects path dependencies of the package in `dir` and theirs, recursively,
+/// so that each library comes after its dependencies.
+fn collect_path_dependencies(
+    dir: &Path,
+    manifest: &Table,
+    workspace: (&Path, &Table),
+    dependencies: &mut Vec<(String, PathBuf)>,
+) -> Result<(), String> {
+    let declared = get_table(manifest, &["dependencies"]).into_iter().flatten();
+    for (key, value) in declared {
+        let Some(value) = value.as_table() else {
+            continue;
+        };
+        let dep_dir = match value.get("path").and_then(Value::as_str) {
+            Some(path) => dir.join(path),
+            None if value.get("workspace").and_then(Value::as_bool) == Some(true) => {
+                let (root_dir, root) = workspace;
+                let path = get_table(root, &["workspace", "dependencies", key])
+                    .and_then(|dep| dep.get("path"))
+                    .and_then(Value::as_str);
+                match path {
+                    Some(path) => root_dir.join(path),
+                    None => continue,
+                }
+            }
+            None => continue,
+        };
+        let name = key.replace('-', "_");
+        if dependencies.iter().any(|(n, _)| *n == name) {
+            continue;
+        }
+        let dep_manifest = read_manifest(&dep_dir.join("Cargo.toml"))?;
+        let Some(path) = lib_path(&dep_dir, &dep_manifest) else {
+            return Err(format!("dependency `{}` has no library target", key));
+        };
+        collect_path_dependencies(&dep_dir, &dep_manifest, workspace, dependencies)?;
+        dependencies.push((name, path));
+    }
+    Ok(())
+}
+
+fn load_package(
+    manifest_path: &Path,
+    workspace: (&Path, &Table),
+) -> Result<Option<Package>, String> {
+    let manifest = read_manifest(manifest_path)?;
+    if !manifest.contains_key("package") {
+        return Ok(None);
+    }
+    let dir = manifest_path.parent().unwrap_or(Path::new("."));
+    let mut targets = vec![];
+    collect_targets(dir, &manifest, TargetKind::Bin, &mut targets)?;
+    collect_targets(dir, &manifest, TargetKind::Example, &mut targets)?;
+    let mut dependencies = vec![];
+    collect_path_dependencies(dir, &manifest, workspace, &mut dependencies)?;
+    if let (Some(name), Some(path)) = (package_name(&manifest), lib_path(dir, &manifest)) {
+        dependencies.push((name.replace('-', "_"), path));
+    }
+    let edition = package_edition(&manifest, workspace.1).to_string();
+    let metadata = get_table(&manifest, &["package", "metadata", "rust_minify"]).cloned();
+    Ok(Some(Package {
+        name: package_name(&manifest).unwrap_or_default().to_string(),
+        targets,
+        dependencies,
+        edition,
+        metadata,
+    }))
+}
+
+fn apply_metadata(option: &mut MinifyOption, metadata: &Table) -> Result<(), String> {
+    for (key, value) in metadata {
+        let values = match value {
+            Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                Value::Boolean(b) => b.to_string(),
+                Value::Integer(i) => i.to_string(),
+                value => return Err(format!("invalid value `{}` for `{}`", value, key)),
+            };
+            cli::set_option(option, key, Some(&value))?;
+        }
+    }
+    Ok(())
+}
+
+fn is_selected(args: &Args, target: &Target) -> bool {
+    let no_selection =
+        !args.all_bins && !args.all_examples && args.bins.is_empty() && args.examples.is_empty();
+    match target.kind {
+        TargetKind::Bin => no_selection || args.all_bins || args.bins.contains(&target.name),
+        TargetKind::Example => args.all_examples || args.examples.contains(&target.name),
+    }
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let manifest_path = match &args.manifest_path {
+        Some(path) => path.clone(),
+        None => find_manifest(&env::current_dir().map_err(|err| err.to_string())?)?,
+    };
+    let (root_path, root) = find_workspace_root(&manifest_path)?;
+    let root_dir = root_path.parent().unwrap_or(Path::new("."));
+    let target_dir = match env::var_os("CARGO_TARGET_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => root_dir.join("target"),
+    };
+
+    let manifests = match get_table(&root, &["workspace"]) {
+        Some(workspace) if root_path == manifest_path && !root.contains_key("package") => {
+            workspace_members(root_dir, workspace)?
+                .into_iter()
+                .map(|dir| dir.join("Cargo.toml"))
+                .collect()
+        }
+        Some(workspace) if root_path == manifest_path => {
+            let mut manifests = vec![manifest_path.clone()];
+            manifests.extend(
+                workspace_members(root_dir, workspace)?
+                    .into_iter()
+                    .map(|dir| dir.join("Cargo.toml"))
+                    .filter(|path| path != &manifest_path),
+            );
+            manifests
+        }
+        _ => vec![manifest_path.clone()],
+    };
+
+    let mut base = MinifyOption::default();
+    let workspace_metadata = get_table(&root, &["workspace", "metadata", "rust_minify"]);
+    if let Some(metadata) = workspace_metadata {
+        apply_metadata(&mut base, metadata)?;
+    }
+
+    let mut packages = vec![];
+    for manifest in manifests {
+        let Some(package) = load_package(&manifest, (root_dir, &root))? else {
+            continue;
+        };
+        let mut option = base.clone();
+        // the edition in the metadata takes precedence over the one of the package
+        if !workspace_metadata.is_some_and(|metadata| metadata.contains_key("edition")) {
+            cli::set_option(&mut option, "edition", Some(&package.edition))?;
+        }
+        if let Some(metadata) = &package.metadata {
+            apply_metadata(&mut option, metadata)?;
+        }
+        let mut flags = args.flags.iter().cloned();
+        while let Some(flag) = flags.next() {
+            cli::parse_option_arg(&mut option, &flag, &mut flags)?;
+        }
+        packages.push((package, option));
+    }
+
+    let mut jobs = vec![];
+    for (package, option) in &packages {
+        for target in package
+            .targets
+            .iter()
+            .filter(|target| is_selected(&args, target))
+        {
+            let output = match target.kind {
+                TargetKind::Bin => target_dir.join("minify"),
+                TargetKind::Example => target_dir.join("minify").join("examples"),
+            };
+            jobs.push((
+                output.join(format!("{}.rs", target.name)),
+                target,
+                package,
+                option,
+            ));
+        }
+    }
+    let outputs: Vec<_> = jobs
+        .iter()
+        .map(|(path, _, package, _)| (path.as_path(), package.name.as_str()))
+        .collect();
+    if let Some((path, first, second)) = output_collision(&outputs) {
+        return Err(format!(
+            "packages `{}` and `{}` would both write `{}`; select one with `--manifest-path`",
+            first,
+            second,
+            path.display()
+        ));
+    }
+    let mut found = vec![];
+    for (path, target, package, option) in jobs {
+        minify_target(target, &package.dependencies, &path, option)?;
+        found.push((target.kind, target.name.clone()));
+    }
+
+    let missing = args
+        .bins
+        .iter()
+        .map(|name| (TargetKind::Bin, name))
+        .chain(args.examples.iter().map(|name| (TargetKind::Example, name)))
+        .find(|&(kind, name)| !found.iter().any(|(k, n)| *k == kind && n == name));
+    match missing {
+        Some((TargetKind::Bin, name)) => Err(format!("no bin target named `{}`", name)),
+        Some((TargetKind::Example, name)) => Err(format!("no example target named `{}`", name)),
+        None => Ok(()),
+    }
+}
+
+/// Returns an output path shared by two targets, with the names of their packages.
+fn output_collision<'a>(outputs: &[(&'a Path, &'a str)]) -> Option<(&'a Path, &'a str, &'a str)> {
+    outputs
+        .iter()
+        .enumerate()
+        .find_map(|(i, &(path, package))| {
+            let (_, other) = outputs[..i].iter().find(|(p, _)| *p == path)?;
+            Some((path, *other, package))
+        })
+}
+
+fn minify_target(
+    target: &Target,
+    dependencies: &[(String, PathBuf)],
+    path: &Path,
+    option: &MinifyOption,
+) -> Result<(), String> {
+    let bundler = dependencies
+        .iter()
+        .fold(Bundler::new(&target.path), |bundler, (name, path)| {
+            bundler.dependency(name, path)
+        });
+    let minified = bundler.minify(option).map_err(|err| err.to_string())?;
+    let output = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(output)
+        .map_err(|err| format!("failed to create `{}`: {}", output.display(), err))?;
+    fs::write(path, minified)
+        .map_err(|err| format!("failed to write `{}`: {}", path.display(), err))?;
+    eprintln!(
+        "    Minified {} -> {}",
+        target.path.display(),
+        path.display()
+    );
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match parse_args(env::args().skip(1)) {
+        Ok(Command::Run(args)) => match run(args) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                ExitCode::FAILURE
+            }
+        },
+        Ok(Command::Help) => {
+            print!("{}", usage());
+            ExitCode::SUCCESS
+        }
+        Ok(Command::Version) => {
+            println!("cargo-minify {}", env!("CARGO_PKG_VERSION"));
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, usage());
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn args(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            args(&[
+                "minify",
+                "--bin",
+                "a",
+                "--example=b",
+                "--remove-skip",
+                "--all-bins"
+            ]),
+            Ok(Command::Run(Args {
+                bins: vec!["a".to_string()],
+                all_bins: true,
+                examples: vec!["b".to_string()],
+                flags: vec!["--remove-skip".to_string()],
+                ..Default::default()
+            }))
+        );
+        assert!(args(&["--bin"]).is_err());
+        assert!(args(&["--unknown"]).is_err());
+    }
+
+    #[test]
+    fn test_apply_metadata() {
+        let metadata: Table = "remove-skip = true\nadd-rustfmt-skip = false"
+            .parse()
+            .unwrap();
+        let mut option = MinifyOption::default();
+        apply_metadata(&mut option, &metadata).unwrap();
+        assert!(option.remove_skip);
+        assert!(!option.add_rustfmt_skip);
+
+        let metadata: Table = "unknown = true".parse().unwrap();
+        assert!(apply_metadata(&mut option, &metadata).is_err());
+    }
+
+    #[test_case("[package]\nedition = \"2021\"", "", "2021"; "package")]
+    #[test_case(
+        "[package]\nedition.workspace = true",
+        "[workspace.package]\nedition = \"2018\"",
+        "2018";
+        "workspace"
+    )]
+    #[test_case("[package]\nname = \"a\"", "", "2015"; "default")]
+    fn test_package_edition(manifest: &str, workspace: &str, expected: &str) {
+        let manifest: Table = manifest.parse().unwrap();
+        let workspace: Table = workspace.parse().unwrap();
+        assert_eq!(package_edition(&manifest, &workspace), expected);
+    }
+
+    #[test]
+    fn test_output_collision() {
+        let a = Path::new("target/minify/a.rs");
+        let b = Path::new("target/minify/b.rs");
+        assert_eq!(output_collision(&[(a, "x"), (b, "x")]), None);
+        assert_eq!(
+            output_collision(&[(a, "x"), (b, "x"), (a, "y")]),
+            Some((a, "x", "y"))
+        );
+    }
+}
@@ -0,0 +1,678 @@
+use crate::{
+    marker::{LineColumn, LinedSource, SpanCollector},
+    minify_opt, MinifyOption,
+};
+use fxhash::FxHashMap;
+use proc_macro2::{Ident, Spacing, Span, TokenStream, TokenTree};
+use quote::ToTokens;
+use std::{
+    fmt, fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+use syn::{
+    ext::IdentExt, parse_file, spanned::Spanned, Attribute, Expr, ExprLit, Item, ItemMacro,
+    ItemMod, Lit, Meta,
+};
+
+const BOM: &str = "\u{feff}";
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, io::Error),
+    Syn(PathBuf, syn::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(path, err) => write!(f, "failed to read `{}`: {}", path.display(), err),
+            Error::Syn(path, err) => {
+                let start = err.span().start();
+                write!(
+                    f,
+                    "{}:{}:{}: {}",
+                    path.display(),
+                    start.line,
+                    start.column + 1,
+                    err
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(_, err) => Some(err),
+            Error::Syn(_, err) => Some(err),
+        }
+    }
+}
+
+/// Reads the crate root `path` and minifies it with every out-of-line module inlined.
+pub fn minify_file(path: &Path, option: &MinifyOption) -> Result<String, Error> {
+    Bundler::new(path).minify(option)
+}
+
+/// Reads the crate root `path` and replaces every `mod foo;` with `mod foo{...}`
+/// holding the content of `foo.rs`, `foo/mod.rs` or the file given by `#[path]`.
+pub fn bundle(path: &Path) -> Result<String, Error> {
+    Bundler::new(path).bundle()
+}
+
+/// Bundles a crate and its local library crates into a single file.
+///
+/// Each library is embedded as a top-level `mod <name>`. `crate::` paths inside a library,
+/// also in the transcribers of `macro_rules!`, become `crate::<name>::`, and `<name>::` paths in
+/// every crate become `crate::<name>::`.
+#[derive(Debug, Clone)]
+pub struct Bundler {
+    root: PathBuf,
+    dependencies: Vec<(String, PathBuf)>,
+}
+
+impl Bundler {
+    /// Creates a bundler of the crate whose root source file is `root`.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            dependencies: vec![],
+        }
+    }
+
+    /// Embeds the library crate referred to as `name` whose root source file is `path`.
+    pub fn dependency(mut self, name: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        self.dependencies.push((name.into(), path.into()));
+        self
+    }
+
+    /// Bundles the crate, embedding the libraries in the order given by [`Bundler::dependency`]
+    /// before the items of the root crate.
+    ///
+    /// `#[macro_export]` macros of a library are re-exported from its module, and a library
+    /// imported by `#[macro_use] extern crate` gets `#[macro_use]` on its module instead;
+    /// so a library should be listed before the libraries using its macros.
+    /// Two crates exporting macros of the same name are reported as an error.
+    pub fn bundle(&self) -> Result<String, Error> {
+        let externs: Vec<_> = self
+            .dependencies
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        let root = self.bundle_crate(&self.root, None, &externs)?;
+        if externs.is_empty() {
+            return Ok(root.content);
+        }
+        let mut crates = vec![];
+        let mut macro_use = root.macro_use.clone();
+        // `#[macro_export]` macros of every crate are defined at the root of the bundled crate
+        let mut exported: FxHashMap<String, String> = root
+            .macro_exports
+            .iter()
+            .map(|ident| (ident.to_string(), "the root crate".to_string()))
+            .collect();
+        for (name, path) in &self.dependencies {
+            let bundled = self.bundle_crate(path, Some(name), &externs)?;
+            macro_use.extend(bundled.macro_use.iter().cloned());
+            for ident in &bundled.macro_exports {
+                if let Some(other) = exported.insert(ident.to_string(), format!("`{}`", name)) {
+                    let err = syn::Error::new(
+                        ident.span(),
+                        format!("macro `{}` is also exported by {}", ident, other),
+                    );
+                    return Err(Error::Syn(path.clone(), err));
+                }
+            }
+            crates.push((name, bundled));
+        }
+
+        let mut buf = String::with_capacity(root.content.len());
+        buf.push_str(&root.content[..root.insert_at]);
+        for (name, bundled) in crates {
+            buf.push('\n');
+            if macro_use.contains(name) {
+                buf.push_str("#[macro_use]\n");
+            }
+            buf.push_str(&format!("mod {}{{\n{}\n", name, bundled.content.trim_end()));
+            if !bundled.macro_exports.is_empty() {
+                let names: Vec<String> =
+                    bundled.macro_exports.iter().map(Ident::to_string).collect();
+                buf.push_str(&format!("pub use crate::{{{}}};\n", names.join(",")));
+            }
+            buf.push_str("}\n");
+        }
+        buf.push_str(&root.content[root.insert_at..]);
+        Ok(buf)
+    }
+
+    pub fn minify(&self, option: &MinifyOption) -> Result<String, Error> {
+        let content = self.bundle()?;
+        minify_opt(&content, option).map_err(|err| Error::Syn(self.root.clone(), err))
+    }
+
+    fn bundle_crate(
+        &self,
+        path: &Path,
+        krate: Option<&str>,
+        externs: &[&str],
+    ) -> Result<BundledCrate, Error> {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let content = ModResolver.resolve(path, dir)?;
+        if krate.is_none() && externs.is_empty() {
+            return Ok(BundledCrate {
+                content,
+                ..Default::default()
+            });
+        }
+        let file = parse_file(&content).map_err(|err| Error::Syn(path.to_path_buf(), err))?;
+        let source = LinedSource::new(&content);
+        let insert_at = match file.attrs.last() {
+            Some(attr) => span_range(&source, attr.span(), path)?.end,
+            None if file.shebang.is_some() => content.find('\n').unwrap_or(content.len()),
+            None => 0,
+        };
+        let mut sc = SpanCollector::new();
+        sc.collect(&file);
+        let mut rewriter = PathRewriter {
+            krate,
+            externs,
+            path,
+            source: &source,
+            macro_bodies: sc.tokens,
+            macro_use: vec![],
+            macro_exports: vec![],
+            edits: vec![],
+        };
+        rewriter.rewrite_items(&file.items)?;
+        rewriter.rewrite_tokens(file.into_token_stream())?;
+        Ok(BundledCrate {
+            content: apply_edits(&content, rewriter.edits),
+            insert_at,
+            macro_use: rewriter.macro_use,
+            macro_exports: rewriter.macro_exports,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct BundledCrate {
+    content: String,
+    /// The offset after the inner attributes, where the libraries are embedded.
+    insert_at: usize,
+    /// The crates imported by `#[macro_use] extern crate`.
+    macro_use: Vec<String>,
+    /// The names of `#[macro_export]` macros.
+    macro_exports: Vec<Ident>,
+}
+
+struct ModResolver;
+
+impl ModResolver {
+    /// Resolves the file `path`, whose child modules are located in `dir`.
+    fn resolve(&self, path: &Path, dir: &Path) -> Result<String, Error> {
+        let content = fs::read_to_string(path).map_err(|err| Error::Io(path.to_path_buf(), err))?;
+        let content = content.strip_prefix(BOM).unwrap_or(&content);
+        let file = parse_file(content).map_err(|err| Error::Syn(path.to_path_buf(), err))?;
+        let source = LinedSource::new(content);
+        let file_dir = path.parent().unwrap_or(Path::new(""));
+        let mut edits = vec![];
+        self.resolve_items(&file.items, path, file_dir, dir, &source, &mut edits)?;
+        Ok(apply_edits(content, edits))
+    }
+
+    /// Collects the edits inlining the modules declared in `items`.
+    ///
+    /// `path_dir` is the base of `#[path]` attributes and `dir` is the directory of child modules.
+    fn resolve_items(
+        &self,
+        items: &[Item],
+        path: &Path,
+        path_dir: &Path,
+        dir: &Path,
+        source: &LinedSource,
+        edits: &mut Vec<(Range<usize>, String)>,
+    ) -> Result<(), Error> {
+        for item in items {
+            let Item::Mod(item) = item else {
+                continue;
+            };
+            let name = item.ident.unraw().to_string();
+            let path_attr = item.attrs.iter().find_map(path_attribute);
+            match &item.content {
+                Some((_, items)) => {
+                    let dir = match &path_attr {
+                        Some((_, p)) => dir.join(p),
+                        None => dir.join(&name),
+                    };
+                    self.resolve_items(items, path, &dir, &dir, source, edits)?;
+                }
+                None => {
+                    let (file, child_dir) = match &path_attr {
+                        Some((_, p)) => {
+                            let file = path_dir.join(p);
+                            let dir = file.parent().unwrap_or(Path::new("")).to_path_buf();
+                            (file, dir)
+                        }
+                        None => match find_mod_file(dir, &name) {
+                            Some(found) => found,
+                            None if has_cfg(item) => continue,
+                            None => {
+                                let err = syn::Error::new(
+                                    item.ident.span(),
+                                    format!(
+                                        "file not found for module `{}` in `{}`",
+                                        name,
+                                        dir.display()
+                                    ),
+                                );
+                                return Err(Error::Syn(path.to_path_buf(), err));
+                            }
+                        },
+                    };
+                    let content = self.resolve(&file, &child_dir)?;
+                    if let Some((attr, _)) = path_attr {
+                        edits.push((span_range(source, attr.span(), path)?, String::new()));
+                    }
+                    let semi = item.semi.as_ref().map(Spanned::span);
+                    let semi = span_range(source, semi.unwrap_or_else(|| item.span()), path)?;
+                    edits.push((semi, format!("{{\n{}\n}}", content.trim_end())));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Rewrites paths of a crate embedded as a module of the bundled crate.
+struct PathRewriter<'a> {
+    /// The name of the embedded crate, `None` for the root crate.
+    krate: Option<&'a str>,
+    externs: &'a [&'a str],
+    path: &'a Path,
+    source: &'a LinedSource<'a>,
+    /// The token ranges of macros found by [`SpanCollector`], where `$crate` is rewritten.
+    macro_bodies: Vec<Range<LineColumn>>,
+    macro_use: Vec<String>,
+    macro_exports: Vec<Ident>,
+    edits: Vec<(Range<usize>, String)>,
+}
+
+impl PathRewriter<'_> {
+    /// Removes `extern crate` of embedded crates, which are no longer external,
+    /// and collects `#[macro_export]` macros.
+    fn rewrite_items(&mut self, items: &[Item]) -> Result<(), Error> {
+        for item in items {
+            match item {
+                Item::Macro(ItemMacro {
+                    attrs,
+                    ident: Some(ident),
+                    mac,
+                    ..
+                }) if mac.path.is_ident("macro_rules")
+                    && attrs
+                        .iter()
+                        .any(|attr| attr.path().is_ident("macro_export")) =>
+                {
+                    self.macro_exports.push(ident.clone());
+                }
+                Item::ExternCrate(item)
+                    if self.externs.contains(&item.ident.to_string().as_str()) =>
+                {
+                    if item
+                        .attrs
+                        .iter()
+                        .any(|attr| attr.path().is_ident("macro_use"))
+                    {
+                        self.macro_use.push(item.ident.to_string());
+                    }
+                    let range = span_range(self.source, item.span(), self.path)?;
+                    let replacement = match &item.rename {
+                        Some((_, rename)) => {
+                            format!(
+                                "{}use crate::{} as {};",
+                                item.vis.to_token_stream(),
+                                item.ident,
+                                rename
+                            )
+                        }
+                        None => String::new(),
+                    };
+                    self.edits.push((range, replacement));
+                }
+                Item::Mod(ItemMod {
+                    content: Some((_, items)),
+                    ..
+                }) => self.rewrite_items(items)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrites paths in `tokens`.
+    fn rewrite_tokens(&mut self, tokens: TokenStream) -> Result<(), Error> {
+        let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+        for (i, tt) in tokens.iter().enumerate() {
+            match tt {
+                TokenTree::Group(group) => {
+                    let is_macro_rules = i >= 3
+                        && matches!(&tokens[i - 3], TokenTree::Ident(ident) if ident == "macro_rules")
+                        && matches!(&tokens[i - 2], TokenTree::Punct(punct) if punct.as_char() == '!');
+                    if is_macro_rules {
+                        self.rewrite_macro_rules(group.stream())?;
+                    } else {
+                        self.rewrite_tokens(group.stream())?;
+                    }
+                }
+                TokenTree::Ident(ident) if is_path_sep(&tokens, i + 1) => {
+                    let after_dollar = i >= 1
+                        && matches!(&tokens[i - 1], TokenTree::Punct(punct) if punct.as_char() == '$');
+                    let after_dot = i >= 1
+                        && matches!(&tokens[i - 1], TokenTree::Punct(punct) if punct.as_char() == '.');
+                    let name = ident.to_string();
+                    match self.krate {
+                        Some(krate) if after_dollar && name == "crate" => {
+                            let start: LineColumn = ident.span().start().into();
+                            if self.macro_bodies.iter().any(|range| range.contains(&start)) {
+                                let end = self.pos(ident.span(), false)?;
+                                self.edits.push((end..end, format!("::{}", krate)));
+                            }
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    if after_dollar || after_dot {
+                        continue;
+                    }
+                    match self.krate {
+                        Some(krate) if name == "crate" => {
+                            let end = self.pos(ident.span(), false)?;
+                            self.edits.push((end..end, format!("::{}", krate)));
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    if !self.externs.contains(&name.as_str()) {
+                        continue;
+                    }
+                    if i >= 2 && is_path_sep(&tokens, i - 2) {
+                        if is_leading_path_sep(&tokens, i - 2) {
+                            let start = self.pos(tokens[i - 2].span(), true)?;
+                            let end = self.pos(tokens[i - 1].span(), false)?;
+                            self.edits.push((start..end, "crate::".to_string()));
+                        }
+                    } else {
+                        let start = self.pos(ident.span(), true)?;
+                        self.edits.push((start..start, "crate::".to_string()));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrites paths in the transcribers of the rules of a `macro_rules!`, leaving the matchers
+    /// whose tokens are matched literally.
+    fn rewrite_macro_rules(&mut self, tokens: TokenStream) -> Result<(), Error> {
+        let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+        for (i, tt) in tokens.iter().enumerate() {
+            if let TokenTree::Group(group) = tt {
+                let after_arrow = i >= 2
+                    && matches!(&tokens[i - 2], TokenTree::Punct(punct) if punct.as_char() == '=')
+                    && matches!(&tokens[i - 1], TokenTree::Punct(punct) if punct.as_char() == '>');
+                if after_arrow {
+                    self.rewrite_tokens(group.stream())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn pos(&self, span: Span, start: bool) -> Result<usize, Error> {
+        let range = span_range(self.source, span, self.path)?;
+        Ok(if start { range.start } else { range.end })
+    }
+}
+
+fn is_path_sep(tokens: &[TokenTree], i: usize) -> bool {
+    matches!(
+        (tokens.get(i), tokens.get(i + 1)),
+        (Some(TokenTree::Punct(p0)), Some(TokenTree::Punct(p1)))
+            if p0.as_char() == ':' && p0.spacing() == Spacing::Joint && p1.as_char() == ':'
+    )
+}
+
+/// Returns whether the `::` at `i` starts a path like `::std::mem`.
+fn is_leading_path_sep(tokens: &[TokenTree], i: usize) -> bool {
+    match i.checked_sub(1).map(|i| &tokens[i]) {
+        Some(TokenTree::Ident(ident)) => [
+            "use", "in", "as", "return", "break", "else", "mut", "ref", "dyn", "impl", "where",
+        ]
+        .iter()
+        .any(|kw| ident == kw),
+        Some(TokenTree::Punct(punct)) => punct.as_char() != '>',
+        Some(TokenTree::Group(_)) => false,
+        _ => true,
+    }
+}
+
+/// Returns the file of the module `name` and the directory of its child modules.
+fn find_mod_file(dir: &Path, name: &str) -> Option<(PathBuf, PathBuf)> {
+    let file = dir.join(format!("{}.rs", name));
+    if file.is_file() {
+        return Some((file, dir.join(name)));
+    }
+    let file = dir.join(name).join("mod.rs");
+    if file.is_file() {
+        return Some((file, dir.join(name)));
+    }
+    None
+}
+
+fn path_attribute(attr: &Attribute) -> Option<(&Attribute, String)> {
+    match &attr.meta {
+        Meta::NameValue(nv) if nv.path.is_ident("path") => match &nv.value {
+            Expr::Lit(ExprLit {
+                lit: Lit::Str(s), ..
+            }) => Some((attr, s.value())),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn has_cfg(item: &ItemMod) -> bool {
+    item.attrs.iter().any(|attr| attr.path().is_ident("cfg"))
+}
+
+fn span_range(source: &LinedSource, span: Span, path: &Path) -> Result<Range<usize>, Error> {
+    let start: LineColumn = span.start().into();
+    let end: LineColumn = span.end().into();
+    match (source.pos(&start), source.pos(&end)) {
+        (Ok(start), Ok(end)) => Ok(start..end),
+        (Err(err), _) | (_, Err(err)) => {
+            Err(Error::Syn(path.to_path_buf(), syn::Error::new(span, err)))
+        }
+    }
+}
+
+fn apply_edits(content: &str, mut edits: Vec<(Range<usize>, String)>) -> String {
+    edits.sort_by_key(|(range, _)| range.start);
+    let mut buf = String::with_capacity(content.len());
+    let mut last = 0;
+    for (range, replacement) in edits {
+        buf.push_str(&content[last..range.start]);
+        buf.push_str(&replacement);
+        last = range.end;
+    }
+    buf.push_str(&content[last..]);
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = env::temp_dir().join(format!("rust_minify-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (path, content) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_minify_file() -> Result<(), Error> {
+        let root = write_files(
+            "mod",
+            &[
+                (
+                    "src/main.rs",
+                    "mod a;\nmod b;\n#[path = \"x/c.rs\"]\nmod c;\nfn main() {}\n",
+                ),
+                ("src/a.rs", "pub mod d;\npub fn a() {} // a\n"),
+                ("src/a/d.rs", "pub fn d() {}\n"),
+                ("src/b/mod.rs", "pub mod e {\n    pub mod f;\n}\n"),
+                ("src/b/e/f.rs", "pub fn f() {}\n"),
+                ("src/x/c.rs", "#![allow(dead_code)]\nmod g;\n"),
+                ("src/x/g.rs", "fn g() {}\n"),
+            ],
+        );
+        let minified = minify_file(&root.join("src/main.rs"), &MinifyOption::default());
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            minified?,
+            "mod a{pub mod d{pub fn d(){}}pub fn a(){}}mod b{pub mod e{pub mod f{pub fn f(){}}}}mod c{#![allow(dead_code)]mod g{fn g(){}}}fn main(){}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_bundle_keeps_skipped_source() -> Result<(), Error> {
+        let root = write_files(
+            "skip",
+            &[
+                (
+                    "lib.rs",
+                    "mod a;\n#[rust_minify::skip]\nfn b() {\n    // b\n}\n",
+                ),
+                ("a.rs", "fn a() {\n    // a\n}\n"),
+            ],
+        );
+        let minified = minify_file(&root.join("lib.rs"), &MinifyOption::default());
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            minified?,
+            "mod a{fn a(){}}\n#[rust_minify::skip]\nfn b() {\n    // b\n}\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_bundler() -> Result<(), Error> {
+        let root = write_files(
+            "deps",
+            &[
+                (
+                    "main.rs",
+                    "extern crate lib as l;\nuse lib::a;\nfn main() {\n    a(::lib::B);\n    l::c::d();\n}\n",
+                ),
+                (
+                    "lib/lib.rs",
+                    "pub mod c;\npub struct B;\npub fn a(_: crate::B) {\n    crate::c::d();\n    util::e();\n}\n",
+                ),
+                ("lib/c.rs", "pub(crate) fn d() {\n    self::super::B;\n}\n"),
+                (
+                    "util.rs",
+                    "macro_rules! m {\n    (crate::f) => {\n        crate::f()\n    };\n}\npub fn e() {}\n",
+                ),
+            ],
+        );
+        let bundled = Bundler::new(root.join("main.rs"))
+            .dependency("lib", root.join("lib/lib.rs"))
+            .dependency("util", root.join("util.rs"))
+            .minify(&MinifyOption::default());
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            bundled?,
+            "mod lib{pub mod c{pub(crate)fn d(){self::super::B;}}pub struct B;pub fn a(_:crate::lib::B){crate::lib::c::d();crate::util::e();}}mod util{macro_rules!m{(crate::f)=>{crate::util::f()};}pub fn e(){}}use crate::lib as l;use crate::lib::a;fn main(){a(crate::lib::B);l::c::d();}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_bundler_macros() -> Result<(), Error> {
+        let root = write_files(
+            "macros",
+            &[
+                (
+                    "main.rs",
+                    "#![allow(unused)]\n#[macro_use]\nextern crate b;\nfn main() {\n    a::m!();\n    n!();\n}\n",
+                ),
+                (
+                    "a.rs",
+                    "#[macro_export]\nmacro_rules! m {\n    () => {\n        $crate::f()\n    };\n}\npub fn f() {}\n",
+                ),
+                (
+                    "b.rs",
+                    "#[macro_export]\nmacro_rules! n {\n    () => {\n        a::m!()\n    };\n}\n",
+                ),
+            ],
+        );
+        let bundled = Bundler::new(root.join("main.rs"))
+            .dependency("a", root.join("a.rs"))
+            .dependency("b", root.join("b.rs"))
+            .minify(&MinifyOption::default());
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            bundled?,
+            "#![allow(unused)]mod a{#[macro_export]macro_rules!m{()=>{$crate::a::f()};}pub fn f(){}pub use crate::{m};}#[macro_use]mod b{#[macro_export]macro_rules!n{()=>{crate::a::m!()};}pub use crate::{n};}fn main(){crate::a::m!();n!();}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_bundler_macro_export_conflict() {
+        let root = write_files(
+            "conflict",
+            &[
+                ("main.rs", "fn main() {}\n"),
+                (
+                    "a.rs",
+                    "#[macro_export]\nmacro_rules! m {\n    () => {};\n}\n",
+                ),
+                (
+                    "b.rs",
+                    "#[macro_export]\nmacro_rules! m {\n    () => {};\n}\n",
+                ),
+            ],
+        );
+        let bundled = Bundler::new(root.join("main.rs"))
+            .dependency("a", root.join("a.rs"))
+            .dependency("b", root.join("b.rs"))
+            .bundle();
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            bundled.unwrap_err().to_string(),
+            format!(
+                "{}:2:14: macro `m` is also exported by `a`",
+                root.join("b.rs").display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_bundle_missing_module() {
+        let root = write_files("missing", &[("lib.rs", "#[cfg(test)]\nmod t;\nmod a;\n")]);
+        let result = bundle(&root.join("lib.rs"));
+        fs::remove_dir_all(&root).unwrap();
+        assert!(matches!(result, Err(Error::Syn(..))));
+    }
+}
@@ -0,0 +1,375 @@
+use crate::attr::{is_minify_skip, ItemExt};
+use fxhash::FxHashSet;
+use std::mem::take;
+use syn::{
+    parse_str,
+    punctuated::Punctuated,
+    visit_mut::{self, VisitMut},
+    Attribute, Block, Expr, ExprLit, ExprMatch, ExprStruct, Field, FieldPat, FieldValue,
+    FieldsNamed, FieldsUnnamed, File, ForeignItem, ImplItem, Item, ItemEnum, ItemForeignMod,
+    ItemImpl, ItemMod, ItemTrait, Lit, Meta, MetaNameValue, PatStruct, Stmt, Token, TraitItem,
+    Variant,
+};
+
+/// The active configuration which `#[cfg]` and `cfg_attr` are evaluated against.
+///
+/// A name or key is known if it is enabled or in [`known`](CfgSet::known); predicates on the
+/// others are left as they are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CfgSet {
+    /// Enabled names, like `test` or `debug_assertions`.
+    pub names: FxHashSet<String>,
+    /// Enabled `key = "value"` pairs, like `feature = "std"` or `target_os = "linux"`.
+    pub values: FxHashSet<(String, String)>,
+    /// Names and keys which are false unless enabled, `test`, `debug_assertions` and `feature`
+    /// by default.
+    pub known: FxHashSet<String>,
+}
+
+impl Default for CfgSet {
+    fn default() -> Self {
+        Self {
+            names: Default::default(),
+            values: Default::default(),
+            known: ["test", "debug_assertions", "feature"]
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl CfgSet {
+    /// Enables `spec`, written as `name`, `key = "value"` or `key = value`.
+    pub fn enable(&mut self, spec: &str) -> Result<(), syn::Error> {
+        let meta = parse_str::<Meta>(spec)?;
+        let key = meta.path().require_ident()?.to_string();
+        match &meta {
+            Meta::Path(_) => {
+                self.names.insert(key);
+            }
+            Meta::NameValue(MetaNameValue { value, .. }) => {
+                let value = match value {
+                    Expr::Lit(ExprLit {
+                        lit: Lit::Str(lit), ..
+                    }) => lit.value(),
+                    Expr::Path(path) => path.path.require_ident()?.to_string(),
+                    _ => return Err(syn::Error::new_spanned(value, "expected a string")),
+                };
+                self.values.insert((key, value));
+            }
+            Meta::List(_) => {
+                return Err(syn::Error::new_spanned(
+                    meta,
+                    "expected `name` or `key = \"value\"`",
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn is_known(&self, key: &str) -> bool {
+        self.known.contains(key)
+            || self.names.contains(key)
+            || self.values.iter().any(|(k, _)| k == key)
+    }
+
+    /// Evaluates the cfg predicate `meta`, or returns `None` if it is unknown.
+    pub fn eval(&self, meta: &Meta) -> Option<bool> {
+        match meta {
+            Meta::Path(path) => {
+                let name = path.get_ident()?.to_string();
+                self.is_known(&name).then(|| self.names.contains(&name))
+            }
+            Meta::NameValue(MetaNameValue {
+                path,
+                value:
+                    Expr::Lit(ExprLit {
+                        lit: Lit::Str(lit), ..
+                    }),
+                ..
+            }) => {
+                let key = path.get_ident()?.to_string();
+                let enabled = self.values.contains(&(key.clone(), lit.value()));
+                (enabled || self.is_known(&key)).then_some(enabled)
+            }
+            Meta::List(list) => {
+                let args = list
+                    .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                    .ok()?;
+                let mut values = args.iter().map(|meta| self.eval(meta));
+                if list.path.is_ident("all") {
+                    values
+                        .try_fold(Some(true), |all, value| match value {
+                            Some(false) => Err(()),
+                            Some(true) => Ok(all),
+                            None => Ok(None),
+                        })
+                        .unwrap_or(Some(false))
+                } else if list.path.is_ident("any") {
+                    values
+                        .try_fold(Some(false), |any, value| match value {
+                            Some(true) => Err(()),
+                            Some(false) => Ok(any),
+                            None => Ok(None),
+                        })
+                        .unwrap_or(Some(true))
+                } else if list.path.is_ident("not") && args.len() == 1 {
+                    self.eval(&args[0]).map(|value| !value)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Removes items, statements, fields, variants and match arms whose `#[cfg]` is false for `cfg`,
+/// and flattens `cfg_attr` whose predicate is known.
+///
+/// `#[cfg]` attributes which are true are removed. Skipped items are left as they are.
+pub fn eval_cfg(file: &mut File, cfg: &CfgSet) {
+    CfgEvaluator { cfg }.visit_file_mut(file);
+}
+
+struct CfgEvaluator<'a> {
+    cfg: &'a CfgSet,
+}
+
+impl CfgEvaluator<'_> {
+    /// Flattens `cfg_attr` in `attrs` and removes true `#[cfg]`, returning `false` if a `#[cfg]`
+    /// is false.
+    fn eval_attrs(&self, attrs: &mut Vec<Attribute>) -> bool {
+        let mut enabled = true;
+        for attr in take(attrs) {
+            if attr.path().is_ident("cfg_attr") {
+                let Some((predicate, metas)) = parse_cfg_attr(&attr) else {
+                    attrs.push(attr);
+                    continue;
+                };
+                match self.cfg.eval(&predicate) {
+                    Some(true) => {
+                        let mut expanded = metas
+                            .into_iter()
+                            .map(|meta| Attribute {
+                                meta,
+                                ..attr.clone()
+                            })
+                            .collect();
+                        enabled &= self.eval_attrs(&mut expanded);
+                        attrs.extend(expanded);
+                    }
+                    Some(false) => {}
+                    None => attrs.push(attr),
+                }
+            } else if attr.path().is_ident("cfg") {
+                let value = match &attr.meta {
+                    Meta::List(list) => list
+                        .parse_args::<Meta>()
+                        .ok()
+                        .and_then(|meta| self.cfg.eval(&meta)),
+                    _ => None,
+                };
+                match value {
+                    Some(true) => {}
+                    Some(false) => enabled = false,
+                    None => attrs.push(attr),
+                }
+            } else {
+                attrs.push(attr);
+            }
+        }
+        enabled
+    }
+
+    /// Removes the nodes whose `#[cfg]` is false, except the skipped ones.
+    fn retain<T>(&self, nodes: &mut Vec<T>, attrs: fn(&mut T) -> Option<&mut Vec<Attribute>>) {
+        nodes.retain_mut(|node| match attrs(node) {
+            Some(attrs) if !is_minify_skip(attrs) => self.eval_attrs(attrs),
+            _ => true,
+        });
+    }
+
+    fn retain_punctuated<T, P: Default>(
+        &self,
+        nodes: &mut Punctuated<T, P>,
+        attrs: fn(&mut T) -> Option<&mut Vec<Attribute>>,
+    ) {
+        let mut retained: Vec<T> = take(nodes).into_iter().collect();
+        self.retain(&mut retained, attrs);
+        *nodes = retained.into_iter().collect();
+    }
+}
+
+/// Parses `#[cfg_attr(predicate, attr, ...)]`.
+fn parse_cfg_attr(attr: &Attribute) -> Option<(Meta, Vec<Meta>)> {
+    let metas = attr
+        .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+        .ok()?;
+    let mut metas = metas.into_iter();
+    let predicate = metas.next()?;
+    Some((predicate, metas.collect()))
+}
+
+fn stmt_attrs(stmt: &mut Stmt) -> Option<&mut Vec<Attribute>> {
+    match stmt {
+        Stmt::Local(local) => Some(&mut local.attrs),
+        Stmt::Item(item) => item.get_attributes_mut(),
+        Stmt::Expr(expr, _) => expr.get_attributes_mut(),
+        Stmt::Macro(mac) => Some(&mut mac.attrs),
+    }
+}
+
+impl VisitMut for CfgEvaluator<'_> {
+    fn visit_block_mut(&mut self, node: &mut Block) {
+        self.retain(&mut node.stmts, stmt_attrs);
+        visit_mut::visit_block_mut(self, node);
+    }
+
+    fn visit_expr_match_mut(&mut self, node: &mut ExprMatch) {
+        self.retain(&mut node.arms, |arm| Some(&mut arm.attrs));
+        visit_mut::visit_expr_match_mut(self, node);
+    }
+
+    fn visit_expr_struct_mut(&mut self, node: &mut ExprStruct) {
+        self.retain_punctuated(&mut node.fields, |field: &mut FieldValue| {
+            Some(&mut field.attrs)
+        });
+        visit_mut::visit_expr_struct_mut(self, node);
+    }
+
+    fn visit_fields_named_mut(&mut self, node: &mut FieldsNamed) {
+        self.retain_punctuated(&mut node.named, |field: &mut Field| Some(&mut field.attrs));
+        visit_mut::visit_fields_named_mut(self, node);
+    }
+
+    fn visit_fields_unnamed_mut(&mut self, node: &mut FieldsUnnamed) {
+        self.retain_punctuated(&mut node.unnamed, |field: &mut Field| {
+            Some(&mut field.attrs)
+        });
+        visit_mut::visit_fields_unnamed_mut(self, node);
+    }
+
+    fn visit_file_mut(&mut self, node: &mut File) {
+        if !self.eval_attrs(&mut node.attrs) {
+            // a false `#![cfg]` removes the whole crate
+            node.attrs.clear();
+            node.items.clear();
+            return;
+        }
+        self.retain(&mut node.items, Item::get_attributes_mut);
+        visit_mut::visit_file_mut(self, node);
+    }
+
+    fn visit_item_mut(&mut self, node: &mut Item) {
+        if !node.get_attributes().is_some_and(is_minify_skip) {
+            visit_mut::visit_item_mut(self, node);
+        }
+    }
+
+    fn visit_item_enum_mut(&mut self, node: &mut ItemEnum) {
+        self.retain_punctuated(&mut node.variants, |variant: &mut Variant| {
+            Some(&mut variant.attrs)
+        });
+        visit_mut::visit_item_enum_mut(self, node);
+    }
+
+    fn visit_item_foreign_mod_mut(&mut self, node: &mut ItemForeignMod) {
+        self.retain(&mut node.items, ForeignItem::get_attributes_mut);
+        visit_mut::visit_item_foreign_mod_mut(self, node);
+    }
+
+    fn visit_item_impl_mut(&mut self, node: &mut ItemImpl) {
+        self.retain(&mut node.items, ImplItem::get_attributes_mut);
+        visit_mut::visit_item_impl_mut(self, node);
+    }
+
+    fn visit_item_mod_mut(&mut self, node: &mut ItemMod) {
+        if let Some((_, items)) = &mut node.content {
+            self.retain(items, Item::get_attributes_mut);
+        }
+        visit_mut::visit_item_mod_mut(self, node);
+    }
+
+    fn visit_item_trait_mut(&mut self, node: &mut ItemTrait) {
+        self.retain(&mut node.items, TraitItem::get_attributes_mut);
+        visit_mut::visit_item_trait_mut(self, node);
+    }
+
+    fn visit_pat_struct_mut(&mut self, node: &mut PatStruct) {
+        self.retain_punctuated(&mut node.fields, |field: &mut FieldPat| {
+            Some(&mut field.attrs)
+        });
+        visit_mut::visit_pat_struct_mut(self, node);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::ToTokens;
+    use syn::parse_file;
+    use test_case::test_case;
+
+    fn cfg_set() -> CfgSet {
+        let mut cfg = CfgSet::default();
+        cfg.enable("debug_assertions").unwrap();
+        cfg.enable("feature = \"std\"").unwrap();
+        cfg.enable("target_os = linux").unwrap();
+        cfg
+    }
+
+    #[test_case("test", Some(false); "known name")]
+    #[test_case("debug_assertions", Some(true); "enabled name")]
+    #[test_case("unix", None; "unknown name")]
+    #[test_case("feature = \"std\"", Some(true); "enabled value")]
+    #[test_case("feature = \"alloc\"", Some(false); "known key")]
+    #[test_case("target_os = \"windows\"", Some(false); "enabled key")]
+    #[test_case("target_arch = \"x86\"", None; "unknown key")]
+    #[test_case("all(debug_assertions, unix)", None; "all unknown")]
+    #[test_case("all(test, unix)", Some(false); "all false")]
+    #[test_case("any(debug_assertions, unix)", Some(true); "any true")]
+    #[test_case("any(test, unix)", None; "any unknown")]
+    #[test_case("not(test)", Some(true); "not")]
+    fn test_eval(predicate: &str, expected: Option<bool>) {
+        let meta = parse_str::<Meta>(predicate).unwrap();
+        assert_eq!(cfg_set().eval(&meta), expected);
+    }
+
+    #[test_case(
+        "#[cfg(test)] mod tests { fn t() {} } #[cfg(not(test))] fn main() {}",
+        "fn main () { }";
+        "items"
+    )]
+    #[test_case(
+        "fn f() { #[cfg(debug_assertions)] let a = 1; #[cfg(not(debug_assertions))] g(); #[cfg(unix)] h(); }",
+        "fn f () { let a = 1 ; # [cfg (unix)] h () ; }";
+        "statements"
+    )]
+    #[test_case(
+        "struct S { a: u8, #[cfg(test)] b: u8 } enum E { A, #[cfg(test)] B } fn f(s: S) { let S { a, #[cfg(test)] b } = s; S { a, #[cfg(test)] b: 1 }; }",
+        "struct S { a : u8 } enum E { A } fn f (s : S) { let S { a } = s ; S { a } ; }";
+        "fields and variants"
+    )]
+    #[test_case(
+        "fn f(x: u8) { match x { #[cfg(feature = \"std\")] 0 => {} #[cfg(feature = \"alloc\")] 1 => {} _ => {} } }",
+        "fn f (x : u8) { match x { 0 => { } _ => { } } }";
+        "match arms"
+    )]
+    #[test_case(
+        "#[cfg_attr(feature = \"std\", derive(Debug), cfg_attr(test, derive(Clone)))] #[cfg_attr(test, derive(Copy))] #[cfg_attr(unix, inline)] struct S; impl S { #[cfg(test)] fn t() {} }",
+        "# [derive (Debug)] # [cfg_attr (unix , inline)] struct S ; impl S { }";
+        "cfg_attr"
+    )]
+    #[test_case(
+        "#[rust_minify::skip] mod a { #[cfg(test)] fn t() {} }",
+        "# [rust_minify :: skip] mod a { # [cfg (test)] fn t () { } }";
+        "skipped"
+    )]
+    fn test_eval_cfg(content: &str, expected: &str) {
+        let mut file = parse_file(content).unwrap();
+        eval_cfg(&mut file, &cfg_set());
+        assert_eq!(file.to_token_stream().to_string(), expected);
+    }
+}
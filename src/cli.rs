@@ -0,0 +1,206 @@
+use rust_minify::{DocComments, MinifyOption};
+
+/// `(name, value name, help)` of the [`MinifyOption`] fields exposed by the binaries.
+///
+/// Each name is accepted as `--<name>` on the command line and as a key of
+/// `[package.metadata.rust_minify]` in `Cargo.toml`.
+pub const OPTIONS: &[(&str, Option<&str>, &str)] = &[
+    (
+        "remove-skip",
+        None,
+        "Remove `#[rust_minify::skip]` and other `rust_minify` attributes",
+    ),
+    (
+        "add-rustfmt-skip",
+        None,
+        "Prepend `#[cfg_attr(any(),rustfmt::skip)]` to each item",
+    ),
+    (
+        "remove-unused",
+        None,
+        "Remove items unreachable from `main` or the roots",
+    ),
+    (
+        "root",
+        Some("NAME"),
+        "Start reachability from the item NAME instead of `main`",
+    ),
+    (
+        "rename-locals",
+        None,
+        "Rename local bindings to the shortest free names",
+    ),
+    (
+        "rename-items",
+        None,
+        "Rename private items, fields and methods across the file",
+    ),
+    (
+        "shorten-generics",
+        None,
+        "Rename generic parameters, lifetimes and labels to the shortest free names",
+    ),
+    (
+        "skip-attr",
+        Some("PATH"),
+        "Treat the attribute PATH like `#[rust_minify::skip]`",
+    ),
+    (
+        "opt-in",
+        None,
+        "Only minify items in or with `#[rust_minify::minify]`",
+    ),
+    (
+        "preserve-comments",
+        None,
+        "Keep `/*! */` comments and comments with `@license` or `@preserve`",
+    ),
+    (
+        "doc-comments",
+        Some("MODE"),
+        "Emit doc comments as `attribute`, `comment` or `strip` them",
+    ),
+    (
+        "edition",
+        Some("YEAR"),
+        "Emit code for the edition YEAR, 2021 by default",
+    ),
+    (
+        "remove-extern-crates",
+        None,
+        "Remove `extern crate` items made redundant by the extern prelude of the edition",
+    ),
+    (
+        "target-rust-version",
+        Some("VERSION"),
+        "Rewrite newer syntax for Rust VERSION, like `1.42`",
+    ),
+    (
+        "inline-format-args",
+        None,
+        "Move identifiers passed to `println!` and the like into the format strings",
+    ),
+    (
+        "macro-grammar",
+        Some("NAME=GRAMMAR"),
+        "Parse the arguments of the macro NAME as `exprs`, `items`, `stmts`, `types`, `typed_pats`, `vec` or `matches`",
+    ),
+    (
+        "eval-cfg",
+        None,
+        "Remove code whose `#[cfg]` is false and flatten `cfg_attr`",
+    ),
+    (
+        "cfg",
+        Some("SPEC"),
+        "Enable `name` or `key=\"value\"` for `--eval-cfg`, which it implies",
+    ),
+];
+
+pub fn options_help() -> String {
+    let mut help = String::new();
+    for (name, value, description) in OPTIONS {
+        let flag = match value {
+            Some(value) => format!("--{} <{}>", name, value),
+            None => format!("--{}", name),
+        };
+        if flag.len() <= 22 {
+            help.push_str(&format!("      {:<22}  {}\n", flag, description));
+        } else {
+            help.push_str(&format!("      {}\n{:30}{}\n", flag, "", description));
+        }
+    }
+    help
+}
+
+fn parse_bool(name: &str, value: Option<&str>) -> Result<bool, String> {
+    match value {
+        None | Some("true") => Ok(true),
+        Some("false") => Ok(false),
+        Some(value) => Err(format!("invalid value `{}` for `{}`", value, name)),
+    }
+}
+
+fn required<'a>(name: &str, value: Option<&'a str>) -> Result<&'a str, String> {
+    value.ok_or_else(|| format!("`{}` requires a value", name))
+}
+
+/// Sets the option `name`; `value` is `None` for a bare flag.
+pub fn set_option(
+    option: &mut MinifyOption,
+    name: &str,
+    value: Option<&str>,
+) -> Result<(), String> {
+    match name {
+        "remove-skip" => option.remove_skip = parse_bool(name, value)?,
+        "add-rustfmt-skip" => option.add_rustfmt_skip = parse_bool(name, value)?,
+        "remove-unused" => option.remove_unused = parse_bool(name, value)?,
+        "root" => option.roots.push(required(name, value)?.to_string()),
+        "rename-locals" => option.rename_locals = parse_bool(name, value)?,
+        "rename-items" => option.rename_items = parse_bool(name, value)?,
+        "shorten-generics" => option.shorten_generics = parse_bool(name, value)?,
+        "opt-in" => option.opt_in = parse_bool(name, value)?,
+        "preserve-comments" => option.preserve_comments = parse_bool(name, value)?,
+        "doc-comments" => {
+            option.doc_comments = match required(name, value)? {
+                "attribute" => DocComments::Attribute,
+                "comment" => DocComments::Comment,
+                "strip" => DocComments::Strip,
+                value => return Err(format!("invalid value `{}` for `{}`", value, name)),
+            }
+        }
+        "skip-attr" => option.skip_attrs.push(required(name, value)?.to_string()),
+        "edition" => option.edition = required(name, value)?.parse()?,
+        "remove-extern-crates" => option.remove_extern_crates = parse_bool(name, value)?,
+        "target-rust-version" => option.target_rust_version = Some(required(name, value)?.parse()?),
+        "inline-format-args" => option.inline_format_args = parse_bool(name, value)?,
+        "macro-grammar" => {
+            let value = required(name, value)?;
+            let (macro_name, grammar) = value
+                .split_once('=')
+                .ok_or_else(|| format!("invalid value `{}` for `{}`", value, name))?;
+            option
+                .macro_grammars
+                .push((macro_name.to_string(), grammar.parse()?));
+        }
+        "eval-cfg" => {
+            option.cfg = parse_bool(name, value)?.then(|| option.cfg.take().unwrap_or_default())
+        }
+        "cfg" => option
+            .cfg
+            .get_or_insert_with(Default::default)
+            .enable(required(name, value)?)
+            .map_err(|err| format!("invalid value for `{}`: {}", name, err))?,
+        _ => return Err(format!("unknown option `{}`", name)),
+    }
+    Ok(())
+}
+
+/// Parses `arg` as `--<name>`, `--<name> <value>` or `--<name>=<value>`.
+///
+/// Returns `Ok(false)` if `arg` is not one of [`OPTIONS`].
+pub fn parse_option_arg(
+    option: &mut MinifyOption,
+    arg: &str,
+    args: &mut impl Iterator<Item = String>,
+) -> Result<bool, String> {
+    let Some(flag) = arg.strip_prefix("--") else {
+        return Ok(false);
+    };
+    let (name, value) = match flag.split_once('=') {
+        Some((name, value)) => (name, Some(value.to_string())),
+        None => (flag, None),
+    };
+    let Some((_, value_name, _)) = OPTIONS.iter().find(|(n, _, _)| *n == name) else {
+        return Ok(false);
+    };
+    let value = match (value, value_name) {
+        (None, Some(_)) => match args.next() {
+            Some(value) => Some(value),
+            None => return Err(format!("`{}` requires a value", arg)),
+        },
+        (value, _) => value,
+    };
+    set_option(option, name, value.as_deref())?;
+    Ok(true)
+}
@@ -0,0 +1,159 @@
+use crate::attr::is_item_skipped;
+use crate::rename;
+use fxhash::FxHashSet;
+use proc_macro2::{TokenStream, TokenTree};
+use quote::ToTokens;
+use std::str::FromStr;
+use syn::{File, Item, ItemExternCrate, Visibility};
+
+/// The edition which the minified code is compiled with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Edition {
+    E2015,
+    E2018,
+    #[default]
+    E2021,
+    E2024,
+}
+
+impl FromStr for Edition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "2015" => Ok(Self::E2015),
+            "2018" => Ok(Self::E2018),
+            "2021" => Ok(Self::E2021),
+            "2024" => Ok(Self::E2024),
+            _ => Err(format!("unknown edition `{}`", s)),
+        }
+    }
+}
+
+impl Edition {
+    /// Returns whether `s` is a strict or reserved keyword in this edition.
+    pub fn is_keyword(self, s: &str) -> bool {
+        match s {
+            "async" | "await" | "dyn" | "try" => self >= Self::E2018,
+            "gen" => self >= Self::E2024,
+            _ => rename::is_keyword(s),
+        }
+    }
+
+    /// Returns whether `r#name` can be written as `name`.
+    ///
+    /// Weak keywords stay raw since they are keywords in some positions.
+    pub fn is_unneeded_raw(self, name: &str) -> bool {
+        !self.is_keyword(name) && !["macro_rules", "raw", "safe", "union"].contains(&name)
+    }
+
+    /// Returns whether `prefix` immediately followed by `next` is lexed differently from them
+    /// separated by a space, where `prefix` is an identifier or a literal.
+    pub fn is_prefix(self, prefix: &str, next: char) -> bool {
+        if self >= Self::E2021 {
+            // reserved prefixes
+            return "#\"'".contains(next);
+        }
+        matches!((prefix, next), ("r" | "br", '#' | '"') | ("b", '\'' | '"'))
+    }
+}
+
+/// Removes `extern crate` declarations at the crate root which the extern prelude makes redundant
+/// from the 2018 edition on.
+///
+/// Declarations with attributes, a visibility or a rename, crates which are not in the extern
+/// prelude and crates referred to by paths like `crate::name` are kept.
+pub fn remove_extern_crates(file: &mut File, edition: Edition) {
+    if edition < Edition::E2018 {
+        return;
+    }
+    let mut scoped = FxHashSet::default();
+    collect_scoped_names(file.to_token_stream(), &mut scoped);
+    file.items.retain(|item| match item {
+        Item::ExternCrate(ItemExternCrate {
+            attrs,
+            vis: Visibility::Inherited,
+            ident,
+            rename: None,
+            ..
+        }) if attrs.is_empty() && !is_item_skipped(item) => {
+            let name = ident.to_string();
+            ["alloc", "proc_macro", "test"].contains(&name.as_str()) || scoped.contains(&name)
+        }
+        _ => true,
+    });
+}
+
+/// Collects names following `crate::`, `self::` or `super::` in `tokens`.
+fn collect_scoped_names(tokens: TokenStream, names: &mut FxHashSet<String>) {
+    let tokens: Vec<_> = tokens.into_iter().collect();
+    for (i, tt) in tokens.iter().enumerate() {
+        match tt {
+            TokenTree::Group(group) => collect_scoped_names(group.stream(), names),
+            TokenTree::Ident(ident) if ["crate", "self", "super"].iter().any(|s| ident == s) => {
+                if let (Some(TokenTree::Punct(p0)), Some(TokenTree::Punct(p1))) =
+                    (tokens.get(i + 1), tokens.get(i + 2))
+                {
+                    if p0.as_char() == ':' && p1.as_char() == ':' {
+                        match tokens.get(i + 3) {
+                            Some(TokenTree::Ident(name)) => {
+                                names.insert(name.to_string());
+                            }
+                            // `use crate::{a, b}`
+                            Some(TokenTree::Group(group)) => {
+                                for tt in group.stream() {
+                                    if let TokenTree::Ident(name) = tt {
+                                        names.insert(name.to_string());
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_file;
+    use test_case::test_case;
+
+    #[test_case(Edition::E2015, "async", false)]
+    #[test_case(Edition::E2018, "async", true)]
+    #[test_case(Edition::E2021, "gen", false)]
+    #[test_case(Edition::E2024, "gen", true)]
+    #[test_case(Edition::E2015, "fn", true)]
+    #[test_case(Edition::E2015, "foo", false)]
+    fn test_is_keyword(edition: Edition, s: &str, expected: bool) {
+        assert_eq!(edition.is_keyword(s), expected);
+    }
+
+    #[test_case(
+        Edition::E2018,
+        "extern crate a; extern crate alloc; #[macro_use] extern crate b; extern crate c as d; pub extern crate e; extern crate f; fn g() { crate::f::h(); }",
+        "extern crate alloc ; # [macro_use] extern crate b ; extern crate c as d ; pub extern crate e ; extern crate f ; fn g () { crate :: f :: h () ; }";
+        "2018"
+    )]
+    #[test_case(
+        Edition::E2015,
+        "extern crate a;",
+        "extern crate a ;";
+        "2015"
+    )]
+    #[test_case(
+        Edition::E2021,
+        "mod m { extern crate a; } use self::{b}; extern crate b;",
+        "mod m { extern crate a ; } use self :: { b } ; extern crate b ;";
+        "nested"
+    )]
+    fn test_remove_extern_crates(edition: Edition, content: &str, expected: &str) {
+        let mut file = parse_file(content).unwrap();
+        remove_extern_crates(&mut file, edition);
+        assert_eq!(file.to_token_stream().to_string(), expected);
+    }
+}
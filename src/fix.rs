@@ -1,10 +1,20 @@
+use crate::attr::{scoped_option, ItemExt};
+use crate::macros::MacroArgs;
+use crate::rename::{
+    assign_names, collect_skipped_idents, collect_token_idents, count_token_idents, ShortNames,
+};
+use fxhash::{FxHashMap, FxHashSet};
+use proc_macro2::{Ident, Spacing, TokenStream, TokenTree};
+use quote::ToTokens;
+use std::mem::take;
 use syn::{
     punctuated::Punctuated, visit_mut, visit_mut::VisitMut, AngleBracketedGenericArguments,
-    BoundLifetimes, Constraint, DataEnum, ExprArray, ExprCall, ExprClosure, ExprMethodCall,
-    ExprStruct, ExprTuple, FieldsNamed, FieldsUnnamed, Generics, Item, ItemEnum, ItemTrait,
-    ItemTraitAlias, LifetimeParam, ParenthesizedGenericArguments, PatOr, PatSlice, PatStruct,
-    PatTuple, PredicateLifetime, PredicateType, Signature, TraitItemType, TypeBareFn,
-    TypeImplTrait, TypeParam, TypeTraitObject, TypeTuple, UseGroup, WhereClause,
+    BoundLifetimes, ConstParam, Constraint, DataEnum, Expr, ExprArray, ExprBreak, ExprCall,
+    ExprClosure, ExprContinue, ExprMethodCall, ExprStruct, ExprTuple, FieldsNamed, FieldsUnnamed,
+    GenericParam, Generics, ImplItem, Item, ItemEnum, ItemTrait, ItemTraitAlias, Label, Lifetime,
+    LifetimeParam, Macro, ParenthesizedGenericArguments, PatOr, PatSlice, PatStruct, PatTuple,
+    Path, PredicateLifetime, PredicateType, Signature, TraitBound, TraitItem, TraitItemType,
+    TypeBareFn, TypeImplTrait, TypeParam, TypeTraitObject, TypeTuple, UseGroup, WhereClause,
 };
 
 pub fn remove_trailing_punct<T, P>(punctuated: &mut Punctuated<T, P>) {
@@ -116,6 +126,21 @@ impl VisitMut for Visitor {
         visit_mut::visit_lifetime_param_mut(self, node);
     }
 
+    fn visit_macro_mut(&mut self, node: &mut Macro) {
+        if let Some(mut args) = MacroArgs::parse(node) {
+            match &mut args {
+                MacroArgs::Exprs(exprs) => remove_trailing_punct(exprs),
+                MacroArgs::Matches { trailing, .. } => *trailing = None,
+                MacroArgs::Types(types) => remove_trailing_punct(types),
+                MacroArgs::TypedPats(pats) => remove_trailing_punct(pats),
+                MacroArgs::Repeat(..) | MacroArgs::Items(_) | MacroArgs::Stmts(_) => {}
+            }
+            args.visit_mut(self);
+            node.tokens = args.into_token_stream();
+        }
+        visit_mut::visit_macro_mut(self, node);
+    }
+
     fn visit_parenthesized_generic_arguments_mut(
         &mut self,
         node: &mut ParenthesizedGenericArguments,
@@ -209,3 +234,391 @@ impl VisitMut for Visitor {
         visit_mut::visit_where_clause_mut(self, node);
     }
 }
+
+/// Renames generic type and const parameters, lifetimes and labels to the shortest names
+/// which are free in their scopes.
+///
+/// Names appearing in macro invocations and attributes of the scope or in skipped nodes of the
+/// item are left as they are, as well as the names in `kept`, `'static` and `'_`.
+#[derive(Default)]
+pub struct GenericShortener {
+    /// Generic type and const parameters in scope.
+    types: Vec<String>,
+    /// Lifetimes and labels in scope, without the leading `'`.
+    lifetimes: Vec<String>,
+    /// Whether the names declared in the current item are renamed.
+    enabled: bool,
+    /// Identifiers and lifetimes in the skipped nodes of the item and the names kept by the caller.
+    skipped: FxHashSet<String>,
+}
+
+/// Parameters declared by a scope.
+#[derive(Default)]
+struct Declared {
+    types: Vec<String>,
+    lifetimes: Vec<String>,
+}
+
+impl Declared {
+    fn new(generics: &Generics) -> Self {
+        let mut declared = Self::default();
+        for param in &generics.params {
+            match param {
+                GenericParam::Type(param) => declared.types.push(param.ident.to_string()),
+                GenericParam::Const(param) => declared.types.push(param.ident.to_string()),
+                GenericParam::Lifetime(param) => {
+                    declared.lifetimes.push(param.lifetime.ident.to_string())
+                }
+            }
+        }
+        declared
+    }
+
+    fn lifetimes(lifetimes: &Option<BoundLifetimes>) -> Self {
+        let generics = Generics {
+            params: lifetimes
+                .iter()
+                .flat_map(|bound| bound.lifetimes.iter().cloned())
+                .collect(),
+            ..Default::default()
+        };
+        Self::new(&generics)
+    }
+}
+
+impl GenericShortener {
+    /// Shortens the names in `node` if `enabled`, unless overridden by
+    /// `#[rust_minify::options(shorten_generics = ..)]` on `node` or an item in it.
+    pub fn shorten_item(node: &mut Item, enabled: bool, kept: &FxHashSet<String>) {
+        let mut visitor = Self {
+            enabled,
+            ..Default::default()
+        };
+        visitor.skipped.extend(kept.iter().cloned());
+        collect_skipped_idents(|v| v.visit_item(node), &mut visitor.skipped);
+        visitor.visit_item_mut(node);
+    }
+
+    /// Renames the parameters declared by `node` with `rename`, then visits it with `visit`
+    /// while they are in scope.
+    fn scope<T: ToTokens>(
+        &mut self,
+        node: &mut T,
+        declared: Declared,
+        rename: fn(&mut ParamRenamer, &mut T),
+        visit: fn(&mut Self, &mut T),
+    ) {
+        if !self.enabled {
+            let (n_types, n_lifetimes) = (self.types.len(), self.lifetimes.len());
+            self.types.extend(declared.types);
+            self.lifetimes.extend(declared.lifetimes);
+            visit(self, node);
+            self.types.truncate(n_types);
+            self.lifetimes.truncate(n_lifetimes);
+            return;
+        }
+        let tokens = node.to_token_stream();
+        let mut opaque = FxHashSet::default();
+        collect_opaque_words(tokens.clone(), &mut opaque);
+        let mut counts = FxHashMap::default();
+        count_token_idents(tokens.clone(), &mut counts);
+        let candidates = |names: &[String]| -> Vec<(String, usize)> {
+            names
+                .iter()
+                .filter(|name| {
+                    !opaque.contains(*name) && !self.skipped.contains(*name) && *name != "_"
+                })
+                .map(|name| (name.clone(), counts.get(name).copied().unwrap_or_default()))
+                .collect()
+        };
+
+        let mut taken = FxHashSet::default();
+        collect_token_idents(tokens.clone(), &mut taken);
+        taken.extend(self.types.iter().cloned());
+        let types = assign_names(
+            candidates(&declared.types),
+            &taken,
+            ShortNames::new(ShortNames::UPPER, ShortNames::ALNUM),
+        );
+        let mut taken = FxHashSet::default();
+        collect_lifetimes(tokens, &mut taken);
+        taken.extend(self.lifetimes.iter().cloned());
+        let lifetimes = assign_names(
+            candidates(&declared.lifetimes),
+            &taken,
+            ShortNames::new(ShortNames::LOWER, ShortNames::ALNUM),
+        );
+        rename(
+            &mut ParamRenamer {
+                types: &types,
+                lifetimes: &lifetimes,
+            },
+            node,
+        );
+
+        let (n_types, n_lifetimes) = (self.types.len(), self.lifetimes.len());
+        let renamed = |renames: &FxHashMap<String, String>, name: String| {
+            renames.get(&name).cloned().unwrap_or(name)
+        };
+        self.types
+            .extend(declared.types.into_iter().map(|name| renamed(&types, name)));
+        self.lifetimes.extend(
+            declared
+                .lifetimes
+                .into_iter()
+                .map(|name| renamed(&lifetimes, name)),
+        );
+        visit(self, node);
+        self.types.truncate(n_types);
+        self.lifetimes.truncate(n_lifetimes);
+    }
+}
+
+impl VisitMut for GenericShortener {
+    fn visit_expr_mut(&mut self, node: &mut Expr) {
+        let label = match node {
+            Expr::Block(expr) => &expr.label,
+            Expr::ForLoop(expr) => &expr.label,
+            Expr::Loop(expr) => &expr.label,
+            Expr::While(expr) => &expr.label,
+            _ => &None,
+        };
+        match label {
+            Some(label) => {
+                let declared = Declared {
+                    types: vec![],
+                    lifetimes: vec![label.name.ident.to_string()],
+                };
+                self.scope(
+                    node,
+                    declared,
+                    |renamer, node| LabelRenamer(renamer).visit_expr_mut(node),
+                    visit_mut::visit_expr_mut,
+                );
+            }
+            None => visit_mut::visit_expr_mut(self, node),
+        }
+    }
+
+    fn visit_impl_item_mut(&mut self, node: &mut ImplItem) {
+        let generics = match node {
+            ImplItem::Const(item) => &item.generics,
+            ImplItem::Fn(item) => &item.sig.generics,
+            ImplItem::Type(item) => &item.generics,
+            _ => return visit_mut::visit_impl_item_mut(self, node),
+        };
+        let declared = Declared::new(generics);
+        self.scope(
+            node,
+            declared,
+            |renamer, node| visit_mut::visit_impl_item_mut(renamer, node),
+            visit_mut::visit_impl_item_mut,
+        );
+    }
+
+    fn visit_item_mut(&mut self, node: &mut Item) {
+        // items do not see the parameters of the enclosing items
+        let types = take(&mut self.types);
+        let lifetimes = take(&mut self.lifetimes);
+        let enabled = self.enabled;
+        let attrs = node.get_attributes().unwrap_or_default();
+        self.enabled = scoped_option(attrs, "shorten_generics").unwrap_or(enabled);
+        let generics = match node {
+            Item::Enum(item) => Some(&item.generics),
+            Item::Fn(item) => Some(&item.sig.generics),
+            Item::Impl(item) => Some(&item.generics),
+            Item::Struct(item) => Some(&item.generics),
+            Item::Trait(item) => Some(&item.generics),
+            Item::TraitAlias(item) => Some(&item.generics),
+            Item::Type(item) => Some(&item.generics),
+            Item::Union(item) => Some(&item.generics),
+            _ => None,
+        };
+        match generics {
+            Some(generics) => {
+                let declared = Declared::new(generics);
+                self.scope(
+                    node,
+                    declared,
+                    |renamer, node| visit_mut::visit_item_mut(renamer, node),
+                    visit_mut::visit_item_mut,
+                );
+            }
+            None => visit_mut::visit_item_mut(self, node),
+        }
+        self.types = types;
+        self.lifetimes = lifetimes;
+        self.enabled = enabled;
+    }
+
+    fn visit_macro_mut(&mut self, _: &mut Macro) {}
+
+    fn visit_predicate_type_mut(&mut self, node: &mut PredicateType) {
+        let declared = Declared::lifetimes(&node.lifetimes);
+        self.scope(
+            node,
+            declared,
+            |renamer, node| visit_mut::visit_predicate_type_mut(renamer, node),
+            visit_mut::visit_predicate_type_mut,
+        );
+    }
+
+    fn visit_trait_bound_mut(&mut self, node: &mut TraitBound) {
+        let declared = Declared::lifetimes(&node.lifetimes);
+        self.scope(
+            node,
+            declared,
+            |renamer, node| visit_mut::visit_trait_bound_mut(renamer, node),
+            visit_mut::visit_trait_bound_mut,
+        );
+    }
+
+    fn visit_trait_item_mut(&mut self, node: &mut TraitItem) {
+        let generics = match node {
+            TraitItem::Const(item) => &item.generics,
+            TraitItem::Fn(item) => &item.sig.generics,
+            TraitItem::Type(item) => &item.generics,
+            _ => return visit_mut::visit_trait_item_mut(self, node),
+        };
+        let declared = Declared::new(generics);
+        self.scope(
+            node,
+            declared,
+            |renamer, node| visit_mut::visit_trait_item_mut(renamer, node),
+            visit_mut::visit_trait_item_mut,
+        );
+    }
+
+    fn visit_type_bare_fn_mut(&mut self, node: &mut TypeBareFn) {
+        let declared = Declared::lifetimes(&node.lifetimes);
+        self.scope(
+            node,
+            declared,
+            |renamer, node| visit_mut::visit_type_bare_fn_mut(renamer, node),
+            visit_mut::visit_type_bare_fn_mut,
+        );
+    }
+}
+
+/// Renames generic parameters and lifetimes in a scope, without descending into nested items.
+struct ParamRenamer<'a> {
+    types: &'a FxHashMap<String, String>,
+    lifetimes: &'a FxHashMap<String, String>,
+}
+
+impl ParamRenamer<'_> {
+    fn rename_ident(&self, ident: &mut Ident) {
+        if let Some(name) = self.types.get(&ident.to_string()) {
+            *ident = Ident::new(name, ident.span());
+        }
+    }
+
+    fn rename_lifetime(&self, lifetime: &mut Lifetime) {
+        if let Some(name) = self.lifetimes.get(&lifetime.ident.to_string()) {
+            lifetime.ident = Ident::new(name, lifetime.ident.span());
+        }
+    }
+}
+
+impl VisitMut for ParamRenamer<'_> {
+    fn visit_const_param_mut(&mut self, node: &mut ConstParam) {
+        self.rename_ident(&mut node.ident);
+        visit_mut::visit_const_param_mut(self, node);
+    }
+
+    // labels are renamed in their own scopes
+    fn visit_expr_break_mut(&mut self, node: &mut ExprBreak) {
+        if let Some(expr) = &mut node.expr {
+            self.visit_expr_mut(expr);
+        }
+    }
+
+    fn visit_expr_continue_mut(&mut self, _: &mut ExprContinue) {}
+
+    fn visit_item_mut(&mut self, _: &mut Item) {}
+
+    fn visit_label_mut(&mut self, _: &mut Label) {}
+
+    fn visit_lifetime_mut(&mut self, node: &mut Lifetime) {
+        self.rename_lifetime(node);
+    }
+
+    fn visit_macro_mut(&mut self, _: &mut Macro) {}
+
+    fn visit_path_mut(&mut self, node: &mut Path) {
+        if node.leading_colon.is_none() {
+            if let Some(segment) = node.segments.first_mut() {
+                self.rename_ident(&mut segment.ident);
+            }
+        }
+        visit_mut::visit_path_mut(self, node);
+    }
+
+    fn visit_type_param_mut(&mut self, node: &mut TypeParam) {
+        self.rename_ident(&mut node.ident);
+        visit_mut::visit_type_param_mut(self, node);
+    }
+}
+
+/// Renames a label and its uses, which do not cross closures and nested items.
+struct LabelRenamer<'a, 'b>(&'a ParamRenamer<'b>);
+
+impl VisitMut for LabelRenamer<'_, '_> {
+    fn visit_expr_break_mut(&mut self, node: &mut ExprBreak) {
+        if let Some(label) = &mut node.label {
+            self.0.rename_lifetime(label);
+        }
+        visit_mut::visit_expr_break_mut(self, node);
+    }
+
+    fn visit_expr_closure_mut(&mut self, _: &mut ExprClosure) {}
+
+    fn visit_expr_continue_mut(&mut self, node: &mut ExprContinue) {
+        if let Some(label) = &mut node.label {
+            self.0.rename_lifetime(label);
+        }
+    }
+
+    fn visit_item_mut(&mut self, _: &mut Item) {}
+
+    fn visit_label_mut(&mut self, node: &mut Label) {
+        self.0.rename_lifetime(&mut node.name);
+    }
+
+    fn visit_macro_mut(&mut self, _: &mut Macro) {}
+}
+
+/// Collects the words of macro invocations and attributes, which can not be renamed.
+///
+/// A group following `!` or `#` is taken as a macro invocation or an attribute, which may
+/// also be a negated expression.
+fn collect_opaque_words(tokens: TokenStream, words: &mut FxHashSet<String>) {
+    let mut opaque = false;
+    for tt in tokens {
+        match &tt {
+            TokenTree::Group(group) if opaque => {
+                let text = group.to_string();
+                let split = text.split(|c: char| !c.is_alphanumeric() && c != '_');
+                words.extend(split.filter(|w| !w.is_empty()).map(str::to_string));
+            }
+            TokenTree::Group(group) => collect_opaque_words(group.stream(), words),
+            _ => {}
+        }
+        opaque = matches!(&tt, TokenTree::Punct(p) if p.as_char() == '!' || p.as_char() == '#');
+    }
+}
+
+/// Collects the names of lifetimes and labels in `tokens`, without the leading `'`.
+fn collect_lifetimes(tokens: TokenStream, lifetimes: &mut FxHashSet<String>) {
+    let mut quote = false;
+    for tt in tokens {
+        match &tt {
+            TokenTree::Group(group) => collect_lifetimes(group.stream(), lifetimes),
+            TokenTree::Ident(ident) if quote => {
+                lifetimes.insert(ident.to_string());
+            }
+            _ => {}
+        }
+        quote = matches!(&tt, TokenTree::Punct(p) if p.as_char() == '\'' && p.spacing() == Spacing::Joint);
+    }
+}
@@ -0,0 +1,511 @@
+use crate::attr::{is_minify_skip, ItemExt};
+use crate::edition::Edition;
+use crate::macros::{is_local_macro, std_macro_name};
+use crate::rename::is_keyword;
+use fxhash::FxHashSet;
+use proc_macro2::{Group, TokenStream, TokenTree};
+use quote::ToTokens;
+use std::{mem::take, ops::Range};
+use syn::{
+    parse::Parser,
+    punctuated::Punctuated,
+    visit_mut::{self, VisitMut},
+    Expr, ExprLit, ExprPath, File, ImplItem, Item, Lit, LitStr, Local, Macro, StmtMacro, Token,
+    TraitItem,
+};
+
+/// Macros taking a format string, with the index of the format string in their arguments.
+const FORMAT_MACROS: [(&str, usize); 18] = [
+    ("assert", 1),
+    ("assert_eq", 2),
+    ("assert_ne", 2),
+    ("debug_assert", 1),
+    ("debug_assert_eq", 2),
+    ("debug_assert_ne", 2),
+    ("eprint", 0),
+    ("eprintln", 0),
+    ("format", 0),
+    ("format_args", 0),
+    ("panic", 0),
+    ("print", 0),
+    ("println", 0),
+    ("todo", 0),
+    ("unimplemented", 0),
+    ("unreachable", 0),
+    ("write", 1),
+    ("writeln", 1),
+];
+
+/// Macros whose lone string literal is not a format string before the 2021 edition.
+pub const PANIC_MACROS: [&str; 4] = ["assert", "debug_assert", "panic", "unreachable"];
+
+/// Returns the index of the format string in the arguments of the macro `name`.
+pub fn format_string_index(name: &str) -> Option<usize> {
+    FORMAT_MACROS
+        .iter()
+        .find(|(macro_name, _)| *macro_name == name)
+        .map(|&(_, index)| index)
+}
+
+/// Parses the arguments of a format macro.
+pub fn parse_args(tokens: TokenStream) -> Option<Punctuated<Expr, Token![,]>> {
+    Punctuated::parse_terminated.parse2(tokens).ok()
+}
+
+/// Rewrites the arguments of `mac` and the format macros nested in it with `f`, which takes the
+/// name of the macro and its arguments.
+pub fn rewrite_macro(mac: &mut Macro, f: &mut impl FnMut(&str, TokenStream) -> TokenStream) {
+    let tokens = rewrite_tokens(take(&mut mac.tokens), f);
+    mac.tokens = match std_macro_name(&mac.path) {
+        Some(name) if format_string_index(&name).is_some() => f(&name, tokens),
+        _ => tokens,
+    };
+}
+
+/// Rewrites the arguments of the format macros invoked in `tokens` with `f`.
+fn rewrite_tokens(
+    tokens: TokenStream,
+    f: &mut impl FnMut(&str, TokenStream) -> TokenStream,
+) -> TokenStream {
+    let mut tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    for i in 0..tokens.len() {
+        let TokenTree::Group(group) = &tokens[i] else {
+            continue;
+        };
+        let mut stream = rewrite_tokens(group.stream(), f);
+        if let (Some(TokenTree::Ident(name)), Some(TokenTree::Punct(bang))) = (
+            i.checked_sub(2).map(|i| &tokens[i]),
+            i.checked_sub(1).map(|i| &tokens[i]),
+        ) {
+            let name = name.to_string();
+            // paths like `a::println!` may refer to other macros
+            let in_path = i
+                .checked_sub(3)
+                .is_some_and(|i| matches!(&tokens[i], TokenTree::Punct(p) if p.as_char() == ':'));
+            if bang.as_char() == '!'
+                && !in_path
+                && !is_local_macro(&name)
+                && format_string_index(&name).is_some()
+            {
+                stream = f(&name, stream);
+            }
+        }
+        let mut new = Group::new(group.delimiter(), stream);
+        new.set_span(group.span());
+        tokens[i] = TokenTree::Group(new);
+    }
+    tokens.into_iter().collect()
+}
+
+/// An argument referred to by a format string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgRef {
+    /// The next positional argument, like `{}`.
+    Next,
+    /// A positional argument, like `{0}` or `{:1$}`.
+    Index(usize),
+    /// A named or captured argument, like `{x}` or `{:.x$}`.
+    Name(String),
+    /// The precision from the next positional argument, `{:.*}`.
+    Star,
+}
+
+/// A reference to an argument in a format string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatArg {
+    /// The byte range of the reference, which is empty for [`ArgRef::Next`].
+    pub range: Range<usize>,
+    pub arg: ArgRef,
+    /// Whether the argument is a width or precision.
+    pub count: bool,
+}
+
+fn is_ident(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && s != "_"
+        && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Parses `s` at `offset` as an argument, or returns `None` if it is not one.
+fn parse_arg_ref(s: &str, offset: usize) -> Option<FormatArg> {
+    let arg = if s.is_empty() {
+        ArgRef::Next
+    } else if s.bytes().all(|b| b.is_ascii_digit()) {
+        ArgRef::Index(s.parse().ok()?)
+    } else if is_ident(s) {
+        ArgRef::Name(s.to_string())
+    } else {
+        return None;
+    };
+    Some(FormatArg {
+        range: offset..offset + s.len(),
+        arg,
+        count: false,
+    })
+}
+
+/// Parses a width or precision at the start of `spec`, returning its length and the argument it
+/// refers to.
+fn parse_count(spec: &str, offset: usize) -> (usize, Option<FormatArg>) {
+    let len = spec
+        .find(|c: char| !c.is_alphanumeric() && c != '_')
+        .unwrap_or(spec.len());
+    if spec[len..].starts_with('$') {
+        if let Some(mut arg) = parse_arg_ref(&spec[..len], offset) {
+            arg.count = true;
+            return (len + 1, Some(arg));
+        }
+    }
+    // a literal count
+    (
+        spec.bytes().take_while(|b| b.is_ascii_digit()).count(),
+        None,
+    )
+}
+
+/// Returns the arguments referred to by the format string `fmt`, in the order of the format
+/// specifications, or `None` if it is malformed.
+///
+/// In a specification, [`ArgRef::Star`] comes first, and the width and precision follow the
+/// value.
+pub fn parse_format(fmt: &str) -> Option<Vec<FormatArg>> {
+    let mut args = vec![];
+    let mut i = 0;
+    while i < fmt.len() {
+        let rest = &fmt[i..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            i += 2;
+        } else if rest.starts_with('}') {
+            return None;
+        } else if rest.starts_with('{') {
+            let start = i + 1;
+            let end = start + fmt[start..].find('}')?;
+            let (arg, spec) = match fmt[start..end].split_once(':') {
+                Some((arg, spec)) => (arg, Some(spec)),
+                None => (&fmt[start..end], None),
+            };
+            let value = parse_arg_ref(arg, start)?;
+            let mut counts = vec![];
+            if let Some(spec) = spec {
+                let mut j = start + arg.len() + 1;
+                let skip = |j: &mut usize, chars: &str| {
+                    if fmt[*j..end].starts_with(|c| chars.contains(c)) {
+                        *j += 1;
+                    }
+                };
+                // fill and alignment
+                match spec.char_indices().nth(1) {
+                    Some((k, c)) if "<^>".contains(c) => j += k + 1,
+                    _ => skip(&mut j, "<^>"),
+                }
+                skip(&mut j, "+-");
+                skip(&mut j, "#");
+                if fmt[j..end].starts_with('0') && !fmt[j + 1..end].starts_with('$') {
+                    j += 1;
+                }
+                let (len, width) = parse_count(&fmt[j..end], j);
+                j += len;
+                counts.extend(width);
+                if fmt[j..end].starts_with(".*") {
+                    args.push(FormatArg {
+                        range: j + 1..j + 2,
+                        arg: ArgRef::Star,
+                        count: true,
+                    });
+                } else if fmt[j..end].starts_with('.') {
+                    counts.extend(parse_count(&fmt[j + 1..end], j + 1).1);
+                }
+            }
+            args.push(value);
+            args.extend(counts);
+            i = end + 1;
+        } else {
+            i += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    Some(args)
+}
+
+/// Macros whose positional arguments are inlined by [`inline_format_args`].
+const INLINE_MACROS: [&str; 8] = [
+    "eprint", "eprintln", "format", "panic", "print", "println", "write", "writeln",
+];
+
+/// Moves positional arguments which are plain identifiers into the format strings of `print!`-like
+/// macros, like `println!("{x}")` from `println!("{}", x)`.
+///
+/// The other references to positional arguments are renumbered, and invocations which would not
+/// get shorter are left as they are. `panic!` is rewritten from the 2021 edition on.
+pub fn inline_format_args(file: &mut File, edition: Edition) {
+    FormatInliner { edition }.visit_file_mut(file);
+}
+
+struct FormatInliner {
+    edition: Edition,
+}
+
+/// Returns the name of `expr` if it is a plain identifier which a format string can capture.
+fn plain_ident(expr: &Expr) -> Option<String> {
+    let Expr::Path(ExprPath {
+        attrs, qself, path, ..
+    }) = expr
+    else {
+        return None;
+    };
+    let name = path.get_ident()?.to_string();
+    (attrs.is_empty() && qself.is_none() && !is_keyword(&name) && !name.starts_with("r#"))
+        .then_some(name)
+}
+
+/// Inlines the positional arguments of the format macro `name` which are plain identifiers.
+fn inline_args(name: &str, tokens: TokenStream, edition: Edition) -> TokenStream {
+    if !INLINE_MACROS.contains(&name) || (name == "panic" && edition < Edition::E2021) {
+        return tokens;
+    }
+    let (Some(index), Some(args)) = (format_string_index(name), parse_args(tokens.clone())) else {
+        return tokens;
+    };
+    let Some(Expr::Lit(ExprLit {
+        lit: Lit::Str(fmt), ..
+    })) = args.iter().nth(index)
+    else {
+        return tokens;
+    };
+    let mut value = fmt.value();
+    let Some(refs) = parse_format(&value) else {
+        return tokens;
+    };
+    if refs.iter().any(|r| r.arg == ArgRef::Star) {
+        return tokens;
+    }
+    let rest: Vec<&Expr> = args.iter().skip(index + 1).collect();
+    let positional = rest
+        .iter()
+        .take_while(|arg| !matches!(arg, Expr::Assign(_)))
+        .count();
+    let named: FxHashSet<String> = rest[positional..]
+        .iter()
+        .filter_map(|arg| match arg {
+            Expr::Assign(assign) => plain_ident(&assign.left),
+            _ => None,
+        })
+        .collect();
+    // the names of the inlined arguments by their indices
+    let names: Vec<Option<String>> = rest[..positional]
+        .iter()
+        .map(|arg| plain_ident(arg).filter(|name| !named.contains(name)))
+        .collect();
+    if names.iter().all(Option::is_none) {
+        return tokens;
+    }
+    let new_indices: Vec<usize> = names
+        .iter()
+        .scan(0, |kept, name| {
+            let index = *kept;
+            *kept += name.is_none() as usize;
+            Some(index)
+        })
+        .collect();
+
+    let mut replacements = vec![];
+    let (mut next, mut new_next) = (0, 0);
+    for r in refs {
+        let (index, implicit) = match r.arg {
+            ArgRef::Next => {
+                next += 1;
+                (next - 1, true)
+            }
+            ArgRef::Index(index) => (index, false),
+            _ => continue,
+        };
+        match names.get(index) {
+            Some(Some(name)) => replacements.push((r.range, name.clone())),
+            Some(None) => {
+                let new = new_indices[index];
+                if implicit && new == new_next {
+                    new_next += 1;
+                } else if implicit || new != index {
+                    replacements.push((r.range, new.to_string()));
+                }
+            }
+            // an invalid format string is left to the compiler
+            None => return tokens,
+        }
+    }
+    for (range, replacement) in replacements.into_iter().rev() {
+        value.replace_range(range, &replacement);
+    }
+
+    let span = fmt.span();
+    let mut new_args = Punctuated::<Expr, Token![,]>::new();
+    for (i, arg) in args.iter().enumerate() {
+        let arg = if i == index {
+            Expr::Lit(ExprLit {
+                attrs: vec![],
+                lit: Lit::Str(LitStr::new(&value, span)),
+            })
+        } else if i > index && names.get(i - index - 1).is_some_and(Option::is_some) {
+            continue;
+        } else {
+            arg.clone()
+        };
+        if !new_args.is_empty() {
+            new_args.push_punct(Token![,](span));
+        }
+        new_args.push_value(arg);
+    }
+    let new_tokens = new_args.into_token_stream();
+    if new_tokens.to_string().len() < tokens.to_string().len() {
+        new_tokens
+    } else {
+        tokens
+    }
+}
+
+impl VisitMut for FormatInliner {
+    fn visit_expr_mut(&mut self, node: &mut Expr) {
+        if !node.get_attributes().is_some_and(is_minify_skip) {
+            visit_mut::visit_expr_mut(self, node);
+        }
+    }
+
+    fn visit_impl_item_mut(&mut self, node: &mut ImplItem) {
+        if !node.get_attributes().is_some_and(is_minify_skip) {
+            visit_mut::visit_impl_item_mut(self, node);
+        }
+    }
+
+    fn visit_item_mut(&mut self, node: &mut Item) {
+        if !node.get_attributes().is_some_and(is_minify_skip) {
+            visit_mut::visit_item_mut(self, node);
+        }
+    }
+
+    fn visit_local_mut(&mut self, node: &mut Local) {
+        if !is_minify_skip(&node.attrs) {
+            visit_mut::visit_local_mut(self, node);
+        }
+    }
+
+    fn visit_macro_mut(&mut self, node: &mut Macro) {
+        let edition = self.edition;
+        rewrite_macro(node, &mut |name, tokens| inline_args(name, tokens, edition));
+        visit_mut::visit_macro_mut(self, node);
+    }
+
+    fn visit_stmt_macro_mut(&mut self, node: &mut StmtMacro) {
+        if !is_minify_skip(&node.attrs) {
+            visit_mut::visit_stmt_macro_mut(self, node);
+        }
+    }
+
+    fn visit_trait_item_mut(&mut self, node: &mut TraitItem) {
+        if !node.get_attributes().is_some_and(is_minify_skip) {
+            visit_mut::visit_trait_item_mut(self, node);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::quote;
+    use test_case::test_case;
+
+    fn arg(range: Range<usize>, arg: ArgRef, count: bool) -> FormatArg {
+        FormatArg { range, arg, count }
+    }
+
+    #[test_case("a {{b}} c", Some(vec![]); "escaped")]
+    #[test_case("{} {:?} {0}", Some(vec![
+        arg(1..1, ArgRef::Next, false),
+        arg(4..4, ArgRef::Next, false),
+        arg(9..10, ArgRef::Index(0), false),
+    ]); "positional")]
+    #[test_case("{x} {é:>5}", Some(vec![
+        arg(1..2, ArgRef::Name("x".into()), false),
+        arg(5..7, ArgRef::Name("é".into()), false),
+    ]); "named")]
+    #[test_case("{:w$.p$} {:0$}", Some(vec![
+        arg(1..1, ArgRef::Next, false),
+        arg(2..3, ArgRef::Name("w".into()), true),
+        arg(5..6, ArgRef::Name("p".into()), true),
+        arg(10..10, ArgRef::Next, false),
+        arg(11..12, ArgRef::Index(0), true),
+    ]); "counts")]
+    #[test_case("{:-^#08.3x} {:x<5}", Some(vec![
+        arg(1..1, ArgRef::Next, false),
+        arg(13..13, ArgRef::Next, false),
+    ]); "literal counts")]
+    #[test_case("{:.*}", Some(vec![
+        arg(3..4, ArgRef::Star, true),
+        arg(1..1, ArgRef::Next, false),
+    ]); "star")]
+    #[test_case("{", None; "unclosed")]
+    #[test_case("}", None; "unopened")]
+    #[test_case("{a b}", None; "invalid")]
+    fn test_parse_format(fmt: &str, expected: Option<Vec<FormatArg>>) {
+        assert_eq!(parse_format(fmt), expected);
+    }
+
+    #[test]
+    fn test_rewrite_macro() {
+        let mut mac: Macro =
+            syn::parse2(quote!(m!(println!("a"), [format!("b")], vec![1]))).unwrap();
+        let mut names = vec![];
+        rewrite_macro(&mut mac, &mut |name, tokens| {
+            names.push(name.to_string());
+            tokens
+        });
+        assert_eq!(names, ["println", "format"]);
+
+        let mut mac: Macro = syn::parse2(quote!(std::write!(f, "{}", format_args!("a")))).unwrap();
+        let mut names = vec![];
+        rewrite_macro(&mut mac, &mut |name, tokens| {
+            names.push(name.to_string());
+            tokens
+        });
+        assert_eq!(names, ["format_args", "write"]);
+    }
+
+    #[test_case(r#"println!("{} {:?}", x, y)"#, r#"println ! ("{x} {y:?}")"#; "implicit")]
+    #[test_case(r#"print!("{0}{0:>1$}", x, w,)"#, r#"print ! ("{x}{x:>w$}")"#; "counts")]
+    #[test_case(
+        r#"write!(f, "{} {} {}", x, a + 1, y)"#,
+        r#"write ! (f , "{x} {} {y}" , a + 1)"#;
+        "partial"
+    )]
+    #[test_case(
+        r#"format!("{1} {} {}", x, a.b, c = 1)"#,
+        r#"format ! ("{0} {x} {}" , a . b , c = 1)"#;
+        "renumbered"
+    )]
+    #[test_case(r#"println!("{} {x}", x, x = 1)"#, r#"println ! ("{} {x}" , x , x = 1)"#; "named")]
+    #[test_case(r#"println!("{:.*}", 1, x)"#, r#"println ! ("{:.*}" , 1 , x)"#; "star")]
+    #[test_case(r#"println!("{}", self)"#, r#"println ! ("{}" , self)"#; "keyword")]
+    #[test_case(r#"panic!("{}", x)"#, r#"panic ! ("{x}")"#; "panic")]
+    #[test_case(r#"assert!(a, "{}", x)"#, r#"assert ! (a , "{}" , x)"#; "other macro")]
+    #[test_case(
+        r#"m!(eprintln!("{}\n", x))"#,
+        r#"m ! (eprintln ! ("{x}\n"))"#;
+        "nested"
+    )]
+    fn test_inline_format_args(content: &str, expected: &str) {
+        let mut file: File = syn::parse_str(&format!("fn f() {{ {}; }}", content)).unwrap();
+        inline_format_args(&mut file, Edition::E2021);
+        assert_eq!(
+            file.to_token_stream().to_string(),
+            format!("fn f () {{ {} ; }}", expected)
+        );
+    }
+
+    #[test]
+    fn test_inline_format_args_2018() {
+        let mut file: File = syn::parse_str(r#"fn f() { panic!("{}", x); }"#).unwrap();
+        inline_format_args(&mut file, Edition::E2018);
+        assert_eq!(
+            file.to_token_stream().to_string(),
+            r#"fn f () { panic ! ("{}" , x) ; }"#
+        );
+    }
+}
@@ -1,87 +1,363 @@
 pub mod attr;
+pub mod bundle;
+pub mod cfg;
+pub mod edition;
 pub mod fix;
+pub mod format;
+pub mod lower;
+pub mod macros;
 pub mod marker;
+pub mod prune;
+pub mod rename;
+pub mod source_map;
 
-use crate::marker::{LineColumn, SpanCollector};
-use attr::{drain_minify_skip, is_minify_skip, ItemExt};
-use fix::Visitor;
-use fxhash::FxHashSet;
-use marker::LinedSource;
+use crate::marker::{LineColumn, PositionError, Skipped, SpanCollector};
+use attr::{
+    has_scoped_options, is_item_skipped, is_minify_marked, is_minify_skip, scoped_option,
+    take_unmarked_items, with_skip_paths, DrainControlAttrs, ItemExt,
+};
+use edition::Edition;
+use fix::{GenericShortener, Visitor};
+use fxhash::{FxHashMap, FxHashSet};
+use marker::{Comment, LinedSource, SkipRegion};
 use once_cell::sync::Lazy;
-use proc_macro2::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+use proc_macro2::{
+    token_stream, Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree,
+};
 use quote::ToTokens;
-use std::{iter::Peekable, ops::Range, str::FromStr};
-use syn::{parse2, spanned::Spanned, File};
+use source_map::SourceMap;
+use std::{collections::VecDeque, iter::Peekable, mem::take, ops::Range, str::FromStr};
+use syn::{
+    parse2, parse_str, spanned::Spanned, visit_mut::VisitMut, Attribute, File, Item, Lit, Path,
+};
 
 pub fn minify(content: &str) -> Result<String, syn::Error> {
     minify_opt(content, &MinifyOption::default())
 }
 
 pub fn minify_opt(content: &str, option: &MinifyOption) -> Result<String, syn::Error> {
+    minify_state(content, option, false).map(|state| state.buf)
+}
+
+/// Minifies `content` and returns the output with the mappings back to `content`.
+pub fn minify_with_source_map(
+    content: &str,
+    option: &MinifyOption,
+) -> Result<(String, SourceMap), syn::Error> {
+    let state = minify_state(content, option, true)?;
+    // spans do not count the BOM
+    let source = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let source_map = state.source_map(source).unwrap_or_default();
+    Ok((state.buf, source_map))
+}
+
+/// Splits the leading BOM and the shebang line off `content`, like [`syn::parse_file`].
+///
+/// The newline after the shebang is kept in the rest, so that the lines of spans do not change.
+fn split_shebang(content: &str) -> (Option<&str>, &str) {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    match content.strip_prefix("#!") {
+        Some(rest) if !rest.trim_start().starts_with('[') => {
+            let end = content.find('\n').unwrap_or(content.len());
+            (Some(&content[..end]), &content[end..])
+        }
+        _ => (None, content),
+    }
+}
+
+/// Collects the identifiers of the tokens in `regions`, which keep their names since the regions
+/// are emitted as they are.
+///
+/// Returns an error if a region starts or ends inside a token tree.
+fn collect_region_idents(
+    tokens: TokenStream,
+    regions: &[SkipRegion],
+    idents: &mut FxHashSet<String>,
+) -> Result<(), syn::Error> {
+    for tt in tokens {
+        let span = tt.span();
+        let (start, end) = (LineColumn::from(span.start()), LineColumn::from(span.end()));
+        let mut overlapping = regions
+            .iter()
+            .map(|region| &region.range)
+            .filter(|range| range.start < end && start < range.end)
+            .peekable();
+        if overlapping.peek().is_none() {
+            continue;
+        }
+        if overlapping
+            .clone()
+            .any(|range| range.start <= start && end <= range.end)
+        {
+            rename::collect_token_idents(tt.into(), idents);
+            continue;
+        }
+        match &tt {
+            TokenTree::Group(group)
+                if overlapping.all(|range| start < range.start && range.end < end) =>
+            {
+                collect_region_idents(group.stream(), regions, idents)?;
+            }
+            _ => {
+                return Err(syn::Error::new(
+                    span,
+                    "`rust_minify:off` and `rust_minify:on` must not split a group or a token",
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+fn minify_state(content: &str, option: &MinifyOption, mappings: bool) -> Result<State, syn::Error> {
+    let skip_paths = option
+        .skip_attrs
+        .iter()
+        .map(|path| parse_str::<Path>(path))
+        .collect::<Result<Vec<_>, _>>()?;
+    with_skip_paths(&skip_paths, || {
+        macros::with_macro_grammars(&option.macro_grammars, || {
+            minify_file(content, option, mappings)
+        })
+    })
+}
+
+fn minify_file(content: &str, option: &MinifyOption, mappings: bool) -> Result<State, syn::Error> {
+    let (shebang, content) = split_shebang(content);
     let tokens = TokenStream::from_str(content)?;
+    let mut local_macros = vec![];
+    macros::collect_macro_rules(tokens.clone(), &mut local_macros);
+    macros::with_local_macros(&local_macros, || {
+        minify_tokens(content, shebang, tokens, option, mappings)
+    })
+}
+
+fn minify_tokens(
+    content: &str,
+    shebang: Option<&str>,
+    tokens: TokenStream,
+    option: &MinifyOption,
+    mappings: bool,
+) -> Result<State, syn::Error> {
+    let source = LinedSource::new(content);
+    let regions = source.skip_regions();
+    let mut kept = FxHashSet::default();
+    collect_region_idents(tokens.clone(), &regions, &mut kept)?;
     let mut sc = SpanCollector::new();
-    let file = match parse2::<File>(tokens.clone()) {
+    let mut file = match parse2::<File>(tokens.clone()) {
         Ok(file) => file,
         Err(_) => {
             let mut state = State::new_with_capacity(
                 sc,
                 MinifyMode {
                     space: SpaceCollapsing::Token,
+                    docs: option.doc_comments,
+                    edition: option.edition,
                 },
                 content.len(),
             );
+            if mappings {
+                state.record_mappings();
+            }
+            for region in &regions {
+                state.skip_region(region);
+            }
+            if option.preserve_comments {
+                for comment in source.comments().iter().filter(|c| c.is_preserved()) {
+                    state.preserve_comment(comment);
+                }
+            }
+            state.step_shebang(shebang);
             state.step_tokens(tokens);
-            return Ok(state.buf);
+            state.step_remaining_comments();
+            return Ok(state);
         }
     };
+    if is_minify_skip(&file.attrs) {
+        let mut state = State::new_with_capacity(
+            sc,
+            MinifyMode {
+                space: SpaceCollapsing::Token,
+                docs: option.doc_comments,
+                edition: option.edition,
+            },
+            content.len(),
+        );
+        if mappings {
+            state.record_mappings();
+        }
+        state.step_shebang(shebang);
+        let mut skipped = Skipped::new(tokens, option.opt_in);
+        // the whole file including the comments around the tokens
+        skipped.range = LineColumn::new(1 + shebang.is_some() as usize, 0)..source.end();
+        for (start, piece) in skipped
+            .pieces(&source, option.remove_skip)
+            .map_err(|err| syn::Error::new(Span::call_site(), err))?
+        {
+            state.step_verbatim(piece, start);
+        }
+        return Ok(state);
+    }
+    // in the opt-in mode, a marker on the file marks every item
+    let mut unmarked = vec![];
+    if option.opt_in && !is_minify_marked(&file.attrs) {
+        take_unmarked_items(&mut file.items, &mut unmarked);
+    }
+    let unmarked: Vec<Skipped> = unmarked
+        .into_iter()
+        .map(|tokens| Skipped::new(tokens, true))
+        .collect();
+    let unmarked_starts: FxHashSet<LineColumn> =
+        unmarked.iter().map(|skipped| skipped.range.start).collect();
+    if let Some(cfg) = &option.cfg {
+        cfg::eval_cfg(&mut file, cfg);
+    }
+    if option.remove_extern_crates {
+        edition::remove_extern_crates(&mut file, option.edition);
+    }
+    if let Some(version) = option.target_rust_version {
+        lower::lower(&mut file, version, option.edition)?;
+    }
+    if option.inline_format_args
+        && option
+            .target_rust_version
+            .is_none_or(|version| version >= lower::INLINE_FORMAT_ARGS)
+    {
+        format::inline_format_args(&mut file, option.edition);
+    }
+    if option.remove_unused {
+        prune::remove_unreachable(&mut file, &option.roots);
+    }
+    // the passes are run for the items enabling them even if disabled by `option`
+    let scoped = has_scoped_options(&file)?;
+    let file_option = option.with_scoped_options(&file.attrs);
+    if option.rename_locals || scoped {
+        rename::rename_locals(&mut file, option.rename_locals, &kept);
+    }
+    if option.rename_items || scoped {
+        rename::rename_items(&mut file, option.rename_items, &kept);
+    }
+    let mut drain = DrainControlAttrs {
+        marker: option.opt_in,
+    };
+    if option.remove_skip {
+        drain.drain(&mut file.attrs);
+    }
+    sc.marker = option.opt_in;
     sc.collect(&file);
-    let source = LinedSource::new(content);
+    let skipped = take(&mut sc.skipped);
     let mut state = State::new_with_capacity(
         sc,
         MinifyMode {
             space: SpaceCollapsing::Syntax,
+            docs: option.doc_comments,
+            edition: option.edition,
         },
         content.len(),
     );
+    if mappings {
+        state.record_mappings();
+    }
+    // skipped nodes replace the doc comments at their starts
+    if option.preserve_comments {
+        for comment in source.comments().iter().filter(|c| c.is_preserved()) {
+            state.preserve_comment(comment);
+        }
+    }
+    for skipped in unmarked.iter().chain(&skipped) {
+        state
+            .skip(skipped, &source, option.remove_skip)
+            .map_err(|err| syn::Error::new(Span::call_site(), err))?;
+    }
+    for region in &regions {
+        state.skip_region(region);
+    }
+    state.step_shebang(shebang);
+    for attr in file.attrs {
+        state.step_tokens(attr.into_token_stream());
+    }
 
-    let mut is_newline = state.buf.is_empty();
     for mut item in file.items {
-        let cond = if option.remove_skip {
-            item.get_attributes_mut().is_some_and(drain_minify_skip)
-        } else {
-            item.get_attributes().is_some_and(is_minify_skip)
+        let is_unmarked = match &item {
+            Item::Verbatim(tokens) => unmarked_starts.contains(&tokens.span().start().into()),
+            _ => false,
         };
-        if cond {
-            if !is_newline {
-                state.buf.push('\n');
-                is_newline = true;
-            }
-            let span = item.span();
-            if let Some(s) = source.get(&(span.start().into()..span.end().into())) {
-                state.buf.push_str(s);
-                state.buf.push('\n');
-            };
-            let end: LineColumn = span.end().into();
-            while state.tokens.peek().is_some_and(|r| r.end <= end) {
-                state.tokens.next();
+        if !is_unmarked && !is_item_skipped(&item) {
+            let item_option =
+                file_option.with_scoped_options(item.get_attributes().unwrap_or_default());
+            if item_option.shorten_generics || scoped {
+                GenericShortener::shorten_item(&mut item, file_option.shorten_generics, &kept);
+            }
+            if option.remove_skip {
+                drain.visit_item_mut(&mut item);
             }
-            state.prev = PrevToken::None;
-        } else {
-            is_newline = false;
             Visitor::fix_item(&mut item);
-            if option.add_rustfmt_skip {
+            if item_option.add_rustfmt_skip {
                 state.buf.push_str("#[cfg_attr(any(),rustfmt::skip)]");
             }
-            state.step_tokens(item.into_token_stream());
         }
+        state.step_tokens(item.into_token_stream());
     }
-    Ok(state.buf)
+    state.step_remaining_comments();
+    Ok(state)
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct MinifyOption {
     pub remove_skip: bool,
     pub add_rustfmt_skip: bool,
+    /// Removes items unreachable from [`roots`](MinifyOption::roots).
+    pub remove_unused: bool,
+    /// Names of the items from which reachability starts, `main` if empty.
+    pub roots: Vec<String>,
+    /// Renames local bindings in function bodies to the shortest free names.
+    pub rename_locals: bool,
+    /// Renames private items, fields and methods consistently across the file.
+    pub rename_items: bool,
+    /// Renames generic parameters, lifetimes and labels to the shortest free names.
+    pub shorten_generics: bool,
+    /// Paths of attributes which work like `rust_minify::skip`, such as `my_tool::verbatim`.
+    pub skip_attrs: Vec<String>,
+    /// Emits items as they are unless they or an enclosing module have `#[rust_minify::minify]`.
+    /// The names the unmarked items use are not renamed, and `remove_unused` keeps them.
+    pub opt_in: bool,
+    /// Keeps `/*! */` comments and comments with `@license` or `@preserve`.
+    pub preserve_comments: bool,
+    /// How `doc` attributes and doc comments are emitted.
+    pub doc_comments: DocComments,
+    /// Removes the code whose `#[cfg]` is false and flattens `cfg_attr` for the active cfg set.
+    pub cfg: Option<cfg::CfgSet>,
+    /// The edition of the code, which decides the tokens needing a space between them and which
+    /// `r#` and `extern crate` are redundant.
+    pub edition: Edition,
+    /// Removes `extern crate` items which the extern prelude of [`edition`](MinifyOption::edition)
+    /// makes redundant.
+    pub remove_extern_crates: bool,
+    /// Rewrites newer syntax for this Rust release, reporting what can not be rewritten.
+    pub target_rust_version: Option<lower::RustVersion>,
+    /// Moves identifiers passed to `print!`-like macros into their format strings, unless
+    /// [`target_rust_version`](MinifyOption::target_rust_version) is older than Rust 1.58.
+    pub inline_format_args: bool,
+    /// Grammars of the arguments of macros by their names, which are emitted like other code when
+    /// they match. Some macros of the standard library are known without them.
+    pub macro_grammars: Vec<(String, macros::MacroGrammar)>,
+}
+
+impl MinifyOption {
+    /// Returns the options overridden by `#[rust_minify::options(..)]` in `attrs`.
+    ///
+    /// Only the [`SCOPED_OPTIONS`](attr::SCOPED_OPTIONS) are overridden; the minification
+    /// returns an error for the other names.
+    pub fn with_scoped_options(&self, attrs: &[Attribute]) -> Self {
+        let scoped = |name, value| scoped_option(attrs, name).unwrap_or(value);
+        Self {
+            add_rustfmt_skip: scoped("add_rustfmt_skip", self.add_rustfmt_skip),
+            rename_items: scoped("rename_items", self.rename_items),
+            rename_locals: scoped("rename_locals", self.rename_locals),
+            shorten_generics: scoped("shorten_generics", self.shorten_generics),
+            ..self.clone()
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -91,11 +367,42 @@ pub struct State {
     bitwise_and: FxHashSet<LineColumn>,
     tokens: Peekable<std::vec::IntoIter<Range<LineColumn>>>,
     mode: MinifyMode,
+    /// Output byte offsets of the emitted tokens and their original positions.
+    mappings: Option<Vec<(usize, LineColumn)>>,
+    /// Skipped nodes by the start of their first token.
+    verbatim: FxHashMap<LineColumn, Verbatim>,
+    /// The starts of the items, before which preserved comments are emitted.
+    item_starts: FxHashSet<LineColumn>,
+    /// Preserved comments which are not emitted yet, in source order.
+    comments: VecDeque<Verbatim>,
+    /// Skipped regions in source order, whose source is taken when emitted.
+    regions: VecDeque<(Range<LineColumn>, Option<Verbatim>)>,
+}
+
+/// The original source of a skipped node, in pieces with their original positions.
+#[derive(Debug, Clone)]
+struct Verbatim {
+    end: LineColumn,
+    pieces: Vec<(LineColumn, String)>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MinifyMode {
     space: SpaceCollapsing,
+    docs: DocComments,
+    edition: Edition,
+}
+
+/// How `doc` attributes and doc comments are emitted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DocComments {
+    /// Emits them as `#[doc="…"]` attributes.
+    #[default]
+    Attribute,
+    /// Removes `#[doc = ..]` and `#![doc = ..]` attributes, whatever their values, and doc comments.
+    Strip,
+    /// Emits them as `///` or `/**…*/` comments where possible, which rustdoc reads the same.
+    Comment,
 }
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SpaceCollapsing {
@@ -104,8 +411,9 @@ pub enum SpaceCollapsing {
     Token,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 enum PrevToken {
+    #[default]
     None,
     /// Ident or Lit, ends with `.`
     IdentOrLiteral(bool),
@@ -147,6 +455,11 @@ impl State {
             bitwise_and: collector.bitwise_and,
             tokens: collector.tokens.into_iter().peekable(),
             mode,
+            mappings: None,
+            verbatim: Default::default(),
+            item_starts: collector.item_starts,
+            comments: Default::default(),
+            regions: Default::default(),
         }
     }
     pub fn new_with_capacity(collector: SpanCollector, mode: MinifyMode, capacity: usize) -> Self {
@@ -156,13 +469,223 @@ impl State {
             bitwise_and: collector.bitwise_and,
             tokens: collector.tokens.into_iter().peekable(),
             mode,
+            mappings: None,
+            verbatim: Default::default(),
+            item_starts: collector.item_starts,
+            comments: Default::default(),
+            regions: Default::default(),
+        }
+    }
+    /// Records the original position of each emitted token for [`State::source_map`].
+    pub fn record_mappings(&mut self) {
+        self.mappings.get_or_insert_with(Vec::new);
+    }
+    /// Returns the source map of the output minified from `source`, if
+    /// [`State::record_mappings`] was called.
+    pub fn source_map(&self, source: &str) -> Option<SourceMap> {
+        let mappings = self.mappings.clone()?;
+        Some(SourceMap::new(&self.buf, mappings, source))
+    }
+    fn record(&mut self, span: Span) {
+        if let Some(mappings) = &mut self.mappings {
+            // tokens created by the passes have the empty span of `Span::call_site`
+            if span.start() != span.end() {
+                mappings.push((self.buf.len(), span.start().into()));
+            }
+        }
+    }
+    /// Emits the original source of `skipped` instead of its tokens, without the skip attribute
+    /// if `remove_attr` is `true`.
+    pub fn skip(
+        &mut self,
+        skipped: &Skipped,
+        source: &LinedSource,
+        remove_attr: bool,
+    ) -> Result<(), PositionError> {
+        let range = &skipped.range;
+        let pieces = skipped
+            .pieces(source, remove_attr)?
+            .into_iter()
+            .map(|(start, piece)| (start, piece.to_string()))
+            .collect();
+        self.verbatim.insert(
+            range.start,
+            Verbatim {
+                end: range.end,
+                pieces,
+            },
+        );
+        Ok(())
+    }
+    /// Emits the lines of `region` instead of the tokens in it.
+    ///
+    /// Regions must be added in source order.
+    pub fn skip_region(&mut self, region: &SkipRegion) {
+        let verbatim = Verbatim {
+            end: region.range.end,
+            pieces: vec![(region.range.start, region.source.to_string())],
+        };
+        self.regions
+            .push_back((region.range.clone(), Some(verbatim)));
+    }
+    /// Returns `true` if the token at `start` is in a skipped region, which is emitted at its
+    /// first token.
+    fn step_region(&mut self, start: LineColumn) -> bool {
+        while self
+            .regions
+            .front()
+            .is_some_and(|(range, _)| range.end <= start)
+        {
+            self.regions.pop_front();
+        }
+        let verbatim = match self.regions.front_mut() {
+            Some((range, verbatim)) if range.start <= start => verbatim.take(),
+            _ => return false,
+        };
+        if let Some(verbatim) = verbatim {
+            self.step_skipped(verbatim);
+        }
+        true
+    }
+    /// Emits `comment` at the item boundary nearest to its position.
+    ///
+    /// Doc comments replace their `doc` attributes, and the others are emitted before the first
+    /// item after them. Comments must be added in source order.
+    pub fn preserve_comment(&mut self, comment: &Comment) {
+        let verbatim = Verbatim {
+            end: comment.range.end,
+            pieces: vec![(comment.range.start, comment.source.to_string())],
+        };
+        if comment.is_doc() {
+            self.verbatim.insert(comment.range.start, verbatim);
+        } else {
+            self.comments.push_back(verbatim);
+        }
+    }
+    /// Emits the preserved comments ending before `end`.
+    fn step_comments(&mut self, end: LineColumn) {
+        while self
+            .comments
+            .front()
+            .is_some_and(|comment| comment.end <= end)
+        {
+            let comment = self.comments.pop_front().unwrap();
+            self.step_lines(&comment);
+            self.prev = PrevToken::None;
+        }
+    }
+    /// Emits the preserved comments which are not emitted yet.
+    fn step_remaining_comments(&mut self) {
+        while let Some(comment) = self.comments.pop_front() {
+            self.step_lines(&comment);
+        }
+    }
+    /// Emits the original source of a skipped node on its own lines.
+    fn step_skipped(&mut self, verbatim: Verbatim) {
+        // the comments in the node are emitted with it
+        let start = verbatim
+            .pieces
+            .first()
+            .map_or(verbatim.end, |(start, _)| *start);
+        self.step_comments(start);
+        while self
+            .comments
+            .front()
+            .is_some_and(|comment| comment.end <= verbatim.end)
+        {
+            self.comments.pop_front();
+        }
+        self.step_lines(&verbatim);
+        while self.tokens.peek().is_some_and(|r| r.end <= verbatim.end) {
+            self.tokens.next();
+        }
+        self.prev = PrevToken::None;
+    }
+    /// Emits `verbatim` on its own lines.
+    fn step_lines(&mut self, verbatim: &Verbatim) {
+        if !self.buf.is_empty() && !self.buf.ends_with('\n') {
+            self.buf.push('\n');
+        }
+        for (start, piece) in &verbatim.pieces {
+            self.step_verbatim(piece, *start);
+        }
+        self.buf.push('\n');
+    }
+    /// Emits the shebang line of the file, which must be followed by a newline.
+    fn step_shebang(&mut self, shebang: Option<&str>) {
+        if let Some(shebang) = shebang {
+            self.step_verbatim(shebang, LineColumn::new(1, 0));
+            self.buf.push('\n');
+        }
+    }
+    /// Emits `source` as it is, mapping each of its lines to the original position.
+    fn step_verbatim(&mut self, source: &str, start: LineColumn) {
+        for (i, line) in source.split_inclusive('\n').enumerate() {
+            if let Some(mappings) = &mut self.mappings {
+                let column = if i == 0 { start.column } else { 0 };
+                mappings.push((self.buf.len(), LineColumn::new(start.line + i, column)));
+            }
+            self.buf.push_str(line);
         }
     }
     pub fn step_tokens(&mut self, tokens: TokenStream) {
-        for tt in tokens {
-            self.step_token_tree(tt);
+        let mut tokens = tokens.into_iter().peekable();
+        while let Some(tt) = tokens.next() {
+            let start = tt.span().start().into();
+            if self.step_region(start) {
+                continue;
+            }
+            if !self.comments.is_empty() && self.item_starts.contains(&start) {
+                self.step_comments(start);
+            }
+            let verbatim = if self.verbatim.is_empty() {
+                None
+            } else {
+                self.verbatim.remove(&tt.span().start().into())
+            };
+            match verbatim {
+                Some(verbatim) => {
+                    while tokens
+                        .next_if(|tt| LineColumn::from(tt.span().end()) <= verbatim.end)
+                        .is_some()
+                    {}
+                    self.step_skipped(verbatim);
+                }
+                None => match &tt {
+                    TokenTree::Punct(punct)
+                        if punct.as_char() == '#' && self.step_doc(punct, &mut tokens) => {}
+                    _ => self.step_token_tree(tt),
+                },
+            }
         }
     }
+    /// Emits the `doc` attribute starting with `hash` as [`MinifyMode::docs`], returning `false`
+    /// if it is emitted as it is.
+    fn step_doc(&mut self, hash: &Punct, tokens: &mut Peekable<token_stream::IntoIter>) -> bool {
+        if self.mode.docs == DocComments::Attribute {
+            return false;
+        }
+        let Some((inner, doc)) = doc_attr(tokens.clone()) else {
+            return false;
+        };
+        let comment = match self.mode.docs {
+            DocComments::Comment => match doc.and_then(|doc| doc_comment(inner, &doc)) {
+                Some(comment) => Some(comment),
+                None => return false,
+            },
+            _ => None,
+        };
+        tokens.nth(inner as usize);
+        if let Some(comment) = comment {
+            if self.buf.ends_with(['/', '*']) {
+                self.buf.push(' ');
+            }
+            self.record(hash.span());
+            self.buf.push_str(&comment);
+            self.prev = PrevToken::None;
+        }
+        true
+    }
     pub fn step_token_tree(&mut self, tt: TokenTree) {
         self.switch_space_mode(tt.span());
         match tt {
@@ -179,9 +702,11 @@ impl State {
             Delimiter::Bracket => ("[", "]"),
             Delimiter::None => ("", ""),
         };
+        self.record(group.span_open());
         self.buf.push_str(ldel);
         self.prev = PrevToken::None;
         self.step_tokens(group.stream());
+        self.record(group.span_close());
         self.buf.push_str(rdel);
         self.prev = PrevToken::None;
     }
@@ -189,13 +714,29 @@ impl State {
         if matches!(self.prev, PrevToken::IdentOrLiteral(_)) {
             self.buf.push(' ');
         }
-        self.buf.push_str(&ident.to_string());
+        self.record(ident.span());
+        let ident = ident.to_string();
+        match ident.strip_prefix("r#") {
+            Some(name) if self.mode.edition.is_unneeded_raw(name) => self.buf.push_str(name),
+            _ => self.buf.push_str(&ident),
+        }
         self.prev = PrevToken::IdentOrLiteral(false);
     }
     fn step_punct(&mut self, punct: Punct) {
         let needs_space = match &self.prev {
             PrevToken::IdentOrLiteral(true) if punct.as_char() == '.' => true,
-            PrevToken::IdentOrLiteral(_) if "#\"'".contains(punct.as_char()) => true,
+            PrevToken::IdentOrLiteral(_) => self
+                .mode
+                .edition
+                .is_prefix(self.prev_word(), punct.as_char()),
+            // `##` is reserved from 2024 on
+            PrevToken::Punct(prev)
+                if self.mode.edition >= Edition::E2024
+                    && prev.as_char() == '#'
+                    && punct.as_char() == '#' =>
+            {
+                true
+            }
             PrevToken::Punct(prev) if matches!(prev.spacing(), Spacing::Alone) => {
                 match self.mode.space {
                     SpaceCollapsing::Syntax => match (prev.as_char(), punct.as_char()) {
@@ -214,20 +755,38 @@ impl State {
         if needs_space {
             self.buf.push(' ');
         }
+        self.record(punct.span());
         self.buf.push_str(&punct.to_string());
         self.prev = PrevToken::Punct(punct);
     }
     fn step_literal(&mut self, literal: Literal) {
-        if matches!(self.prev, PrevToken::IdentOrLiteral(_)) {
+        let lit = literal.to_string();
+        let needs_space = match &self.prev {
+            PrevToken::IdentOrLiteral(_) => true,
+            // guarded strings like `#"…"#` from 2024 on
+            PrevToken::Punct(prev) => {
+                self.mode.edition >= Edition::E2024 && prev.as_char() == '#' && lit.starts_with('"')
+            }
+            PrevToken::None => false,
+        };
+        if needs_space {
             self.buf.push(' ');
         }
-        let lit = literal.to_string();
         let last_is_dot = lit.ends_with('.');
         let tuple_access = matches!(&self.prev, PrevToken::Punct(punct) if punct.as_char() == '.')
             && lit.chars().next().is_some_and(|c| c.is_ascii_digit());
+        self.record(literal.span());
         self.buf.push_str(&lit);
         self.prev = PrevToken::IdentOrLiteral(last_is_dot | tuple_access);
     }
+    /// Returns the identifier or the suffix of the literal at the end of the output.
+    fn prev_word(&self) -> &str {
+        let start = self
+            .buf
+            .trim_end_matches(|c: char| c == '_' || c.is_alphanumeric())
+            .len();
+        &self.buf[start..]
+    }
     fn switch_space_mode(&mut self, span: Span) {
         match self.mode.space {
             SpaceCollapsing::Syntax => {
@@ -250,9 +809,55 @@ impl State {
     }
 }
 
-impl Default for PrevToken {
-    fn default() -> Self {
-        Self::None
+/// Returns whether the attribute after `#` in `tokens` is inner and its text, if it is
+/// `doc = <expr>`; the text is `None` unless the value is a string literal.
+fn doc_attr(mut tokens: impl Iterator<Item = TokenTree>) -> Option<(bool, Option<String>)> {
+    let mut tt = tokens.next()?;
+    let inner = matches!(&tt, TokenTree::Punct(punct) if punct.as_char() == '!');
+    if inner {
+        tt = tokens.next()?;
+    }
+    let TokenTree::Group(group) = tt else {
+        return None;
+    };
+    if group.delimiter() != Delimiter::Bracket {
+        return None;
+    }
+    let mut stream = group.stream().into_iter();
+    match (stream.next()?, stream.next()?) {
+        (TokenTree::Ident(ident), TokenTree::Punct(eq))
+            if ident == "doc" && eq.as_char() == '=' =>
+        {
+            match (stream.next()?, stream.next()) {
+                (TokenTree::Literal(lit), None) => match Lit::new(lit) {
+                    Lit::Str(lit) => Some((inner, Some(lit.value()))),
+                    _ => Some((inner, None)),
+                },
+                _ => Some((inner, None)),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Returns the shortest doc comment with the text `doc`, if any.
+fn doc_comment(inner: bool, doc: &str) -> Option<String> {
+    if doc.contains('\r') {
+        return None;
+    }
+    // `////` and `/***` are not doc comments, and `/**/` is an empty comment
+    let ambiguous = !inner && doc.starts_with(['/', '*']);
+    let (line, block) = if inner {
+        ("//!", "/*!")
+    } else {
+        ("///", "/**")
+    };
+    if !doc.contains('\n') && (inner || !doc.starts_with('/')) {
+        Some(format!("{}{}\n", line, doc))
+    } else if !ambiguous && !doc.contains("/*") && !doc.contains("*/") {
+        Some(format!("{}{}*/", block, doc))
+    } else {
+        None
     }
 }
 
@@ -344,11 +949,765 @@ mod tests {
         "struct X<'a>(&'a());impl<'a>X<'a>{fn x(&'a self)->impl 'a+Clone{match \"a\"{_=>{macro!(#a #b);}}}}";
         "reserving syntax for rust 2021"
     )]
+    #[test_case(
+        indoc!(r#"
+            #!/usr/bin/env rust-script
+            //! Crate docs
+            #![allow(unused)]
+            fn main() {}
+        "#),
+        "#!/usr/bin/env rust-script\n#![doc=\" Crate docs\"]#![allow(unused)]fn main(){}";
+        "shebang and inner attributes"
+    )]
+    #[test_case(
+        "\u{feff}#![no_std] fn f() {}",
+        "#![no_std]fn f(){}";
+        "byte order mark"
+    )]
+    #[test_case(
+        indoc!(r#"
+            // 日本語のコメント
+            fn a() { let c = 'é'; }
+            #[rust_minify::skip]
+            fn b() { let s = "ü"; }
+        "#),
+        "fn a(){let c='é';}\n#[rust_minify::skip]\nfn b() { let s = \"ü\"; }\n";
+        "multibyte before skipped item"
+    )]
+    #[test_case(
+        indoc!(r#"
+            const C: char = 'é'; #[rust_minify::skip] fn b() { /* 日本語 */ let s  =  "ü"; }
+            fn c() {}
+        "#),
+        "const C:char='é';\n#[rust_minify::skip] fn b() { /* 日本語 */ let s  =  \"ü\"; }\nfn c(){}";
+        "multibyte inside skipped item"
+    )]
+    #[test_case(
+        indoc!(r#"
+            impl S {
+                #[rust_minify::skip]
+                fn a(&self) { 1 + 1 }
+                fn b(&self) { 2 + 2 }
+            }
+        "#),
+        "impl S{\n#[rust_minify::skip]\n    fn a(&self) { 1 + 1 }\nfn b(&self){2+2}}";
+        "impl item"
+    )]
+    #[test_case(
+        indoc!(r#"
+            mod m {
+                #[rust_minify::skip]
+                const A: u8 = 1;
+                trait T { #[rust_minify::skip] fn t( ); }
+            }
+        "#),
+        "mod m{\n#[rust_minify::skip]\n    const A: u8 = 1;\ntrait T{\n#[rust_minify::skip] fn t( );\n}}";
+        "items in nested modules and trait items"
+    )]
+    #[test_case(
+        indoc!(r#"
+            fn f() {
+                let a = 1;
+                #[rust_minify::skip]
+                let b = [ 1, 2 ];
+                #[rust_minify::skip] { a + b[0] }
+            }
+        "#),
+        "fn f(){let a=1;\n#[rust_minify::skip]\n    let b = [ 1, 2 ];\n#[rust_minify::skip] { a + b[0] }\n}";
+        "statements and expressions"
+    )]
+    #[test_case(
+        indoc!(r#"
+            extern "C" {
+                #[rust_minify::skip]
+                fn c( x: i32 );
+            }
+        "#),
+        "extern \"C\"{\n#[rust_minify::skip]\n    fn c( x: i32 );\n}";
+        "foreign item"
+    )]
+    #[test_case(
+        indoc!(r#"
+            const A: u8 = 1;
+            // rust_minify:off
+            const TABLE: [u8; 4] = [
+                1, 2,
+                3, 4,
+            ];
+            // rust_minify:on
+            const B: u8 = 2;
+        "#),
+        "const A:u8=1;\nconst TABLE: [u8; 4] = [\n    1, 2,\n    3, 4,\n];\nconst B:u8=2;";
+        "skip region"
+    )]
+    #[test_case(
+        indoc!(r#"
+            macro_rules! m {
+                () => { 1 + 1 };
+                // rust_minify:off
+                (x) => { 2 + 2 };
+                // rust_minify:on
+            }
+        "#),
+        "macro_rules!m{()=>{1+1};\n    (x) => { 2 + 2 };\n}";
+        "skip region in macro"
+    )]
+    #[test_case(
+        "fn a() {}\n// rust_minify:off\nfn b() { }\n",
+        "fn a(){}\nfn b() { }\n";
+        "skip region to the end"
+    )]
+    #[test_case(
+        indoc!(r#"
+            mod a {
+                #![rust_minify::skip]
+                fn a() { 1 + 1 }
+            }
+            pub mod b { #![cfg_attr(x, rust_minify::skip)] }
+            fn c() { 2 + 2 }
+        "#),
+        "mod a {\n    #![rust_minify::skip]\n    fn a() { 1 + 1 }\n}\npub mod b { #![cfg_attr(x, rust_minify::skip)] }\nfn c(){2+2}";
+        "inner attribute of module"
+    )]
+    #[test_case(
+        indoc!(r#"
+            // comment
+            #![rust_minify::skip]
+            fn a() { 1 + 1 }
+        "#),
+        "// comment\n#![rust_minify::skip]\nfn a() { 1 + 1 }\n";
+        "inner attribute of file"
+    )]
     fn test_minify(content: &str, expected: &str) -> Result<(), syn::Error> {
         assert_eq!(minify(content)?, expected);
         Ok(())
     }
 
+    #[test_case("fn a() {\n// rust_minify:off\n}\nfn b() {}\n"; "start in group")]
+    #[test_case("// rust_minify:off\nfn a() {\n// rust_minify:on\n}\n"; "end in group")]
+    fn test_minify_skip_region_error(content: &str) {
+        let err = minify(content).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`rust_minify:off` and `rust_minify:on` must not split a group or a token"
+        );
+    }
+
+    #[test]
+    fn test_minify_remove_skip() -> Result<(), syn::Error> {
+        let option = MinifyOption {
+            remove_skip: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            minify_opt(
+                "impl S {\n    #[rust_minify::skip]\n    fn a() { 1 + 1 }\n}",
+                &option
+            )?,
+            "impl S{\nfn a() { 1 + 1 }\n}"
+        );
+        assert_eq!(
+            minify_opt(
+                "mod m {\n    #![rust_minify::skip]\n    fn a() {}\n}",
+                &option
+            )?,
+            "mod m {\n    fn a() {}\n}\n"
+        );
+        assert_eq!(
+            minify_opt("#!/bin/sh\n#![rust_minify::skip]\nfn a() { }\n", &option)?,
+            "#!/bin/sh\nfn a() { }\n"
+        );
+        assert_eq!(
+            minify_opt(
+                "#[rust_minify::skip] #[rust_minify::options(rename_locals = false)] fn b() {\n    #[rust_minify::skip] let x = 1;\n}",
+                &option
+            )?,
+            "fn b() {\n    let x = 1;\n}\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_minify_skip_attrs() -> Result<(), syn::Error> {
+        let option = MinifyOption {
+            skip_attrs: vec!["my_tool::verbatim".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            minify_opt(
+                "#[my_tool::verbatim]\nfn a() { 1 + 1 }\nimpl S { #[cfg_attr(x, my_tool::verbatim)] fn b() { 2 } }",
+                &option
+            )?,
+            "#[my_tool::verbatim]\nfn a() { 1 + 1 }\nimpl S{\n#[cfg_attr(x, my_tool::verbatim)] fn b() { 2 }\n}"
+        );
+        let option = MinifyOption {
+            remove_skip: true,
+            ..option
+        };
+        assert_eq!(
+            minify_opt(
+                "mod m {\n    #![my_tool::verbatim]\n    fn a() {}\n}",
+                &option
+            )?,
+            "mod m {\n    fn a() {}\n}\n"
+        );
+        assert_eq!(
+            minify("#[my_tool::verbatim]\nfn a() { 1 + 1 }")?,
+            "#[my_tool::verbatim]fn a(){1+1}"
+        );
+        Ok(())
+    }
+
+    #[test_case(
+        indoc!(r#"
+            fn main() {
+                let x = 1;
+            }
+            #[rust_minify::minify]
+            mod lib {
+                pub fn f() { 1 + 1 }
+            }
+        "#),
+        false,
+        "fn main() {\n    let x = 1;\n}\n#[rust_minify::minify]mod lib{pub fn f(){1+1}}";
+        "marked module"
+    )]
+    #[test_case(
+        indoc!(r#"
+            mod a {
+                fn b() { 1 }
+                #[rust_minify::minify]
+                fn c() { 2 }
+            }
+        "#),
+        true,
+        "mod a{\nfn b() { 1 }\nfn c(){2}}";
+        "marked item in module with remove skip"
+    )]
+    #[test_case(
+        "#![rust_minify::minify]\nfn a() { 1 }",
+        true,
+        "fn a(){1}";
+        "marked file"
+    )]
+    #[test_case(
+        indoc!(r#"
+            fn a() {
+                #[rust_minify::skip]
+                let x = 1;
+            }
+            #[rust_minify::minify]
+            fn b() { 2 }
+        "#),
+        true,
+        "fn a() {\n    let x = 1;\n}\nfn b(){2}";
+        "nested skip in unmarked item with remove skip"
+    )]
+    fn test_minify_opt_in(
+        content: &str,
+        remove_skip: bool,
+        expected: &str,
+    ) -> Result<(), syn::Error> {
+        let option = MinifyOption {
+            opt_in: true,
+            remove_skip,
+            ..Default::default()
+        };
+        assert_eq!(minify_opt(content, &option)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_minify_opt_in_passes() -> Result<(), syn::Error> {
+        let option = MinifyOption {
+            opt_in: true,
+            rename_items: true,
+            remove_unused: true,
+            cfg: Some(Default::default()),
+            ..Default::default()
+        };
+        let content = indoc!(
+            r#"
+            #[cfg_attr(x, derive(Debug))]
+            pub(crate) struct Value(u8);
+            fn main() { show(Value(1)); }
+            #[rust_minify::minify]
+            fn show(value: Value) { print(value.0); }
+            #[rust_minify::minify]
+            fn print(n: u8) {}
+            #[rust_minify::minify]
+            fn unused() {}
+            "#
+        );
+        assert_eq!(
+            minify_opt(content, &option)?,
+            "#[cfg_attr(x, derive(Debug))]\npub(crate) struct Value(u8);\nfn main() { show(Value(1)); }\n#[rust_minify::minify]fn show(value:Value){a(value.0);}#[rust_minify::minify]fn a(n:u8){}"
+        );
+        Ok(())
+    }
+
+    #[test_case(
+        indoc!(r#"
+            #[rust_minify::options(rename_locals = false)]
+            mod m {
+                fn f(value: u8) -> u8 { value }
+            }
+            fn g(value: u8) -> u8 { value }
+        "#),
+        MinifyOption { rename_locals: true, ..Default::default() },
+        "#[rust_minify::options(rename_locals=false)]mod m{fn f(value:u8)->u8{value}}fn g(a:u8)->u8{a}";
+        "disable in module"
+    )]
+    #[test_case(
+        indoc!(r#"
+            fn f<Value>(value: Value) {}
+            #[rust_minify::options(shorten_generics, rename_locals = true)]
+            fn g<Value>(value: Value) {}
+        "#),
+        MinifyOption::default(),
+        "fn f<Value>(value:Value){}#[rust_minify::options(shorten_generics,rename_locals=true)]fn g<A>(a:A){}";
+        "enable on item"
+    )]
+    #[test_case(
+        indoc!(r#"
+            struct Long;
+            #[rust_minify::options(rename_items = false)]
+            mod m { struct Inner; fn f() -> super::Long { super::Long } }
+        "#),
+        MinifyOption { rename_items: true, remove_skip: true, ..Default::default() },
+        "struct Long;mod m{struct Inner;fn f()->super::Long{super::Long}}";
+        "disable items and remove"
+    )]
+    #[test_case(
+        "#![rust_minify::options(add_rustfmt_skip)]\nfn a() {}\n#[rust_minify::options(add_rustfmt_skip = false)]\nfn b() {}",
+        MinifyOption::default(),
+        "#![rust_minify::options(add_rustfmt_skip)]#[cfg_attr(any(),rustfmt::skip)]fn a(){}#[rust_minify::options(add_rustfmt_skip=false)]fn b(){}";
+        "file options"
+    )]
+    fn test_minify_scoped_options(
+        content: &str,
+        option: MinifyOption,
+        expected: &str,
+    ) -> Result<(), syn::Error> {
+        assert_eq!(minify_opt(content, &option)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_minify_scoped_options_error() {
+        let content = "#[rust_minify::options(rename_everything)] fn f() {}";
+        assert!(minify(content).is_err());
+    }
+
+    #[test_case(
+        indoc!(r#"
+            /*!
+             * crate v1.0
+             */
+            // plain comment
+            fn main() {
+                let x = 1; // @preserve x
+                let y = 2;
+            }
+            mod lib {
+                /* @license MIT */
+                pub fn f() {}
+            }
+        "#),
+        "/*!\n * crate v1.0\n */\nfn main(){let x=1;let y=2;}\n// @preserve x\nmod lib{\n/* @license MIT */\npub fn f(){}}";
+        "preserved comments"
+    )]
+    #[test_case(
+        "fn main() {}\n/// @license MIT\nfn f() {}\n// @license end",
+        "fn main(){}\n/// @license MIT\nfn f(){}\n// @license end\n";
+        "doc comment and trailing comment"
+    )]
+    #[test_case(
+        "#[rust_minify::skip]\nfn f() { /* @license */ }\nfn g() {}",
+        "#[rust_minify::skip]\nfn f() { /* @license */ }\nfn g(){}";
+        "comment in skipped item"
+    )]
+    fn test_minify_preserve_comments(content: &str, expected: &str) -> Result<(), syn::Error> {
+        let option = MinifyOption {
+            preserve_comments: true,
+            ..Default::default()
+        };
+        assert_eq!(minify_opt(content, &option)?, expected);
+        Ok(())
+    }
+
+    #[test_case(
+        DocComments::Attribute,
+        "#![doc=\" Crate\"]#[doc=\" Item\"]#[doc(hidden)]#[doc=concat!(\"a\",\"b\")]fn f(){}struct S{#[doc=\" Field\"]x:u8}";
+        "attribute"
+    )]
+    #[test_case(
+        DocComments::Strip,
+        "#[doc(hidden)]fn f(){}struct S{x:u8}";
+        "strip"
+    )]
+    #[test_case(
+        DocComments::Comment,
+        "//! Crate\n/// Item\n#[doc(hidden)]#[doc=concat!(\"a\",\"b\")]fn f(){}struct S{/// Field\nx:u8}";
+        "comment"
+    )]
+    fn test_minify_doc_comments(docs: DocComments, expected: &str) -> Result<(), syn::Error> {
+        let content = indoc!(
+            r#"
+            //! Crate
+            /// Item
+            #[doc(hidden)]
+            #[doc = concat!("a", "b")]
+            fn f() {}
+            struct S {
+                /** Field*/
+                x: u8,
+            }
+        "#
+        );
+        let option = MinifyOption {
+            doc_comments: docs,
+            ..Default::default()
+        };
+        assert_eq!(minify_opt(content, &option)?, expected);
+        Ok(())
+    }
+
+    #[test_case(false, " text", Some("/// text\n"); "line")]
+    #[test_case(true, "/ text", Some("//!/ text\n"); "inner slash")]
+    #[test_case(false, "/ text", None; "outer slash")]
+    #[test_case(false, " a\n b", Some("/** a\n b*/"); "block")]
+    #[test_case(false, "* a\n b", None; "outer star block")]
+    #[test_case(false, " a\n */", None; "block end")]
+    #[test_case(false, " a\r", None; "carriage return")]
+    fn test_doc_comment(inner: bool, doc: &str, expected: Option<&str>) {
+        assert_eq!(doc_comment(inner, doc).as_deref(), expected);
+    }
+
+    #[test]
+    fn test_minify_remove_unused() -> Result<(), syn::Error> {
+        let option = MinifyOption {
+            remove_unused: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            minify_opt(
+                "fn unused() { m!(a); }\nfn main() { n!(Option<usize> = 1); }",
+                &option
+            )?,
+            "fn main(){n!(Option<usize> =1);}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_minify_eval_cfg() -> Result<(), syn::Error> {
+        let mut cfg = cfg::CfgSet::default();
+        cfg.enable("feature = \"std\"")?;
+        let option = MinifyOption {
+            cfg: Some(cfg),
+            ..Default::default()
+        };
+        assert_eq!(
+            minify_opt(
+                indoc! {r#"
+                    #[cfg_attr(feature = "std", derive(Debug))]
+                    struct S(#[cfg(test)] u8);
+                    fn main() {
+                        #[cfg(debug_assertions)]
+                        eprintln!("debug");
+                    }
+                    #[cfg(test)]
+                    mod tests {}
+                "#},
+                &option
+            )?,
+            "#[derive(Debug)]struct S();fn main(){}"
+        );
+        Ok(())
+    }
+
+    #[test_case(
+        Edition::E2015,
+        "fn r#async() { m!(a #b, r #c, b 'd'); r#async(); }",
+        "fn async(){m!(a#b,r #c,b 'd');async();}";
+        "2015"
+    )]
+    #[test_case(
+        Edition::E2021,
+        "fn r#gen() { m!(a #b, # #c, #\"d\"); r#gen(); r#async(); }",
+        "fn gen(){m!(a #b,##c,#\"d\");gen();r#async();}";
+        "2021"
+    )]
+    #[test_case(
+        Edition::E2024,
+        "fn r#gen() { m!(a #b, # #c, # \"d\"); r#union(); }",
+        "fn r#gen(){m!(a #b,# #c,# \"d\");r#union();}";
+        "2024"
+    )]
+    fn test_minify_edition(edition: Edition, content: &str, expected: &str) {
+        let option = MinifyOption {
+            edition,
+            ..Default::default()
+        };
+        assert_eq!(minify_opt(content, &option).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_minify_remove_extern_crates() -> Result<(), syn::Error> {
+        let content = "extern crate a; #[macro_use] extern crate b; fn main() {}";
+        assert_eq!(
+            minify(content)?,
+            "extern crate a;#[macro_use]extern crate b;fn main(){}"
+        );
+        let option = MinifyOption {
+            edition: Edition::E2018,
+            remove_extern_crates: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            minify_opt(content, &option)?,
+            "#[macro_use]extern crate b;fn main(){}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_minify_target_rust_version() -> Result<(), syn::Error> {
+        let option = MinifyOption {
+            target_rust_version: Some("1.42".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            minify_opt(
+                indoc! {r#"
+                    fn main() {
+                        let Some(x) = f() else { return };
+                        println!("{x:>w$}", w = 3);
+                        if let Some(y) = g(x) && y > 1 {
+                            m!(a - > b);
+                        }
+                    }
+                "#},
+                &option
+            )?,
+            "fn main(){let x=match f(){Some(x)=>x,_=>{return}};println!(\"{x:>w$}\",w=3,x=x);if let Some(y)=g(x){if y>1{m!(a- >b);}}}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_minify_inline_format_args() -> Result<(), syn::Error> {
+        let mut option = MinifyOption {
+            inline_format_args: true,
+            ..Default::default()
+        };
+        let content = "fn main() { let x = 1; println!(\"{} {:>1$}\", x, x + 1); }";
+        assert_eq!(
+            minify_opt(content, &option)?,
+            "fn main(){let x=1;println!(\"{x} {:>0$}\",x+1);}"
+        );
+        option.target_rust_version = Some("1.57".parse().unwrap());
+        assert_eq!(
+            minify_opt(content, &option)?,
+            "fn main(){let x=1;println!(\"{} {:>1$}\",x,x+1);}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_minify_local_macros() -> Result<(), syn::Error> {
+        let option = MinifyOption {
+            inline_format_args: true,
+            ..Default::default()
+        };
+        let content = indoc!(
+            r#"
+            macro_rules! print { ($($t:tt)*) => {} }
+            fn main() { let x = 1; print!("{}", x); m!(print!("{}", x)); std::print!("{}", x); }
+            "#
+        );
+        assert_eq!(
+            minify_opt(content, &option)?,
+            "macro_rules!print{($($t:tt)*)=>{}}fn main(){let x=1;print!(\"{}\",x);m!(print!(\"{}\",x));std::print!(\"{x}\");}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_minify_macro_grammars() -> Result<(), syn::Error> {
+        let content = indoc! {r#"
+            fn main() {
+                input! { n: usize, a: [Option<usize>; n], }
+                let v: Vec<Option<usize>> = vec![None, Some(1),];
+                assert!(matches!(v[0], | None), "{}", n & &n,);
+            }
+        "#};
+        assert_eq!(
+            minify(content)?,
+            "fn main(){input!{n:usize,a:[Option<usize>;n],}let v:Vec<Option<usize>>=vec![None,Some(1)];assert!(matches!(v[0],None),\"{}\",n& &n);}"
+        );
+        let option = MinifyOption {
+            macro_grammars: vec![("input".to_string(), macros::MacroGrammar::TypedPats)],
+            ..Default::default()
+        };
+        assert_eq!(
+            minify_opt(content, &option)?,
+            "fn main(){input!{n:usize,a:[Option<usize>;n]}let v:Vec<Option<usize>>=vec![None,Some(1)];assert!(matches!(v[0],None),\"{}\",n& &n);}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_minify_rename_locals() -> Result<(), syn::Error> {
+        let option = MinifyOption {
+            rename_locals: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            minify_opt(
+                "fn main() { let message = 1; let other = message; m!(other); }",
+                &option
+            )?,
+            "fn main(){let a=1;let other=a;m!(other);}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_minify_rename_items() -> Result<(), syn::Error> {
+        let option = MinifyOption {
+            rename_items: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            minify_opt(
+                "struct Wrapper { inner: u8 }\nfn unwrap(w: Wrapper) -> u8 { w.inner }\nfn main() { unwrap(Wrapper { inner: 1 }); }",
+                &option
+            )?,
+            "struct A{a:u8}fn b(w:A)->u8{w.a}fn main(){b(A{a:1});}"
+        );
+        assert_eq!(
+            minify_opt(
+                "use std::ops::Range;\nstruct Span { start: usize, len: usize }\nfn f(r: Range<usize>, total: usize) -> (Span, Range<usize>) { let Range { start, end } = r; (Span { start, len: end - start }, Range { start: total, end: 9 }) }\nfn main() { f(0..1, 2); }",
+                &option
+            )?,
+            "use std::ops::Range;struct A{start:usize,b:usize}fn a(r:Range<usize>,total:usize)->(A,Range<usize>){let Range{start,end}=r;(A{start,b:end-start},Range{start:total,end:9})}fn main(){a(0..1,2);}"
+        );
+        Ok(())
+    }
+
+    #[test_case(
+        "struct Wrapper<'life, TValue, const SIZE: usize>(&'life [TValue; SIZE]);",
+        "struct Wrapper<'a,B,const A:usize>(&'a[B;A]);";
+        "parameters"
+    )]
+    #[test_case(
+        "impl<TItem> Holder<TItem> { fn map<TOut>(self, f: impl Fn(TItem) -> TOut) -> TOut { f(self.0) } }",
+        "impl<A>Holder<A>{fn map<B>(self,f:impl Fn(A)->B)->B{f(self.0)}}";
+        "nested scopes"
+    )]
+    #[test_case(
+        "fn f() { 'outer: loop { 'inner: for _ in 0..1 { break 'outer; } continue 'outer; } }",
+        "fn f(){'a:loop{'b:for _ in 0..1{break 'a;}continue 'a;}}";
+        "labels"
+    )]
+    #[test_case(
+        "fn g<'long>(x: &'long str, y: &'static str, z: &'_ str) -> &'long str { x }",
+        "fn g<'a>(x:&'a str,y:&'static str,z:&'_ str)->&'a str{x}";
+        "static and anonymous lifetimes"
+    )]
+    #[test_case(
+        "fn h<Func, TKeep>(f: Func) where Func: for<'any> Fn(&'any u8) { m!(TKeep); }",
+        "fn h<A,TKeep>(f:A)where A:for<'a>Fn(&'a u8){m!(TKeep);}";
+        "higher-ranked lifetimes and macros"
+    )]
+    fn test_minify_shorten_generics(content: &str, expected: &str) -> Result<(), syn::Error> {
+        let option = MinifyOption {
+            shorten_generics: true,
+            ..Default::default()
+        };
+        assert_eq!(minify_opt(content, &option)?, expected);
+        Ok(())
+    }
+
+    #[test_case(
+        "fn f(value: u8) -> u8 { #[rust_minify::skip] let w = value; w }",
+        "fn a(value:u8)->u8{\n#[rust_minify::skip] let w = value;\nw}";
+        "skipped let"
+    )]
+    #[test_case(
+        "fn f(value: u8) -> u8 { #[rust_minify::skip] { value + 1 } }",
+        "fn a(value:u8)->u8{\n#[rust_minify::skip] { value + 1 }\n}";
+        "skipped expression"
+    )]
+    #[test_case(
+        "fn f(value: u8) { #[rust_minify::skip] m!(value); }",
+        "fn a(value:u8){\n#[rust_minify::skip] m!(value);\n}";
+        "skipped statement macro"
+    )]
+    #[test_case(
+        "struct Helper; struct S; impl S { #[rust_minify::skip] fn make() -> Helper { Helper } }",
+        "struct Helper;struct A;impl A{\n#[rust_minify::skip] fn make() -> Helper { Helper }\n}";
+        "skipped impl item"
+    )]
+    #[test_case(
+        "struct Value; trait T<Item> { #[rust_minify::skip] fn t(v: Item) -> Value { Value } }",
+        "struct Value;trait A<Item>{\n#[rust_minify::skip] fn t(v: Item) -> Value { Value }\n}";
+        "skipped trait item"
+    )]
+    #[test_case(
+        "struct Value; fn f(v: Value) { #[rust_minify::skip] let w: Value = v; }",
+        "struct Value;fn a(v:Value){\n#[rust_minify::skip] let w: Value = v;\n}";
+        "skipped let with type"
+    )]
+    #[test_case(
+        "fn f<'life, TItem>(x: &'life TItem) { #[rust_minify::skip] fn g<TItem>(y: &TItem) {} }",
+        "fn c<'a,TItem>(b:&'a TItem){\n#[rust_minify::skip] fn g<TItem>(y: &TItem) {}\n}";
+        "skipped nested item"
+    )]
+    #[test_case(
+        "fn f(value: u8) -> u8 {\n// rust_minify:off\nlet w = value;\n// rust_minify:on\nw }",
+        "fn a(value:u8)->u8{\nlet w = value;\nw}";
+        "skip region"
+    )]
+    fn test_minify_rename_skipped(content: &str, expected: &str) -> Result<(), syn::Error> {
+        let option = MinifyOption {
+            rename_locals: true,
+            rename_items: true,
+            shorten_generics: true,
+            ..Default::default()
+        };
+        assert_eq!(minify_opt(content, &option)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_minify_with_source_map() -> Result<(), syn::Error> {
+        let content = indoc!(
+            r#"
+            fn main() {
+                let x = 1;
+            }
+            #[rust_minify::skip]
+            fn skipped() {
+                let y = 2;
+            }
+        "#
+        );
+        let (minified, source_map) = minify_with_source_map(content, &MinifyOption::default())?;
+        assert_eq!(
+            minified,
+            "fn main(){let x=1;}\n#[rust_minify::skip]\nfn skipped() {\n    let y = 2;\n}\n"
+        );
+        let lookup = |line, column| source_map.lookup(LineColumn::new(line, column));
+        assert_eq!(lookup(1, 0), Some(LineColumn::new(1, 0)));
+        assert_eq!(lookup(1, 14), Some(LineColumn::new(2, 8)));
+        assert_eq!(lookup(1, 18), Some(LineColumn::new(3, 0)));
+        assert_eq!(lookup(4, 8), Some(LineColumn::new(6, 0)));
+        Ok(())
+    }
+
     #[test]
     fn test_punct_space() {
         // https://docs.rs/syn/latest/src/syn/token.rs.html#791-838
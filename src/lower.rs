@@ -0,0 +1,601 @@
+use crate::attr::{is_minify_skip, ItemExt};
+use crate::edition::Edition;
+use crate::format::{
+    format_string_index, parse_args, parse_format, rewrite_macro, ArgRef, PANIC_MACROS,
+};
+use crate::rename::{collect_token_idents, ShortNames};
+use fxhash::FxHashSet;
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::{quote_spanned, ToTokens};
+use std::{fmt, mem::replace, str::FromStr};
+use syn::{
+    parse_quote_spanned,
+    visit::{self, Visit},
+    visit_mut::{self, VisitMut},
+    BinOp, Block, Expr, ExprBinary, ExprIf, ExprLit, ExprLoop, ExprWhile, ImplItem, Item, ItemImpl,
+    Lit, Local, Macro, Pat, PatIdent, ReturnType, Stmt, StmtMacro, Token, TraitItem, TypeImplTrait,
+};
+
+/// A Rust release, like `1.42`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RustVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl RustVersion {
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+}
+
+impl FromStr for RustVersion {
+    type Err = String;
+
+    /// Parses `1.42` or `1.42.0`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('.').map(|part| part.parse::<u32>().ok());
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(Some(major)), Some(Some(minor)), None | Some(Some(_)), None) => {
+                Ok(Self::new(major, minor))
+            }
+            _ => Err(format!("invalid Rust version `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for RustVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+pub const INLINE_FORMAT_ARGS: RustVersion = RustVersion::new(1, 58);
+const LET_ELSE: RustVersion = RustVersion::new(1, 65);
+const GENERIC_ASSOCIATED_TYPES: RustVersion = RustVersion::new(1, 65);
+const IMPL_TRAIT_IN_TRAITS: RustVersion = RustVersion::new(1, 75);
+const LET_CHAINS: RustVersion = RustVersion::new(1, 88);
+
+/// Rewrites syntax newer than `version` into older equivalents.
+///
+/// `let`-`else`, `if let` and `while let` chains and captured identifiers in format strings are
+/// lowered. `impl Trait` returned from a trait method, `async fn` in a trait, generic associated
+/// types and `let`-`else` with a type annotation are reported as errors. Skipped nodes are left as
+/// they are.
+pub fn lower(file: &mut syn::File, version: RustVersion, edition: Edition) -> syn::Result<()> {
+    let mut lowerer = Lowerer {
+        version,
+        edition,
+        trait_impl: false,
+        error: None,
+    };
+    lowerer.visit_file_mut(file);
+    match lowerer.error {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+struct Lowerer {
+    version: RustVersion,
+    edition: Edition,
+    /// Whether the items are in a trait implementation.
+    trait_impl: bool,
+    error: Option<syn::Error>,
+}
+
+impl Lowerer {
+    /// Reports `tokens` using a feature stabilized in `since`, if it is newer than the target.
+    fn require(&mut self, tokens: impl ToTokens, feature: &str, since: RustVersion) {
+        if self.version < since {
+            let err =
+                syn::Error::new_spanned(tokens, format!("{} requires Rust {}", feature, since));
+            match &mut self.error {
+                Some(error) => error.combine(err),
+                None => self.error = Some(err),
+            }
+        }
+    }
+
+    fn check_method(&mut self, asyncness: Option<Token![async]>, output: &ReturnType) {
+        if let Some(asyncness) = asyncness {
+            self.require(asyncness, "`async fn` in a trait", IMPL_TRAIT_IN_TRAITS);
+        }
+        let mut finder = ImplTraitFinder(None);
+        finder.visit_return_type(output);
+        if let Some(span) = finder.0 {
+            self.require(
+                quote_spanned!(span=> impl),
+                "`impl Trait` returned from a trait method",
+                IMPL_TRAIT_IN_TRAITS,
+            );
+        }
+    }
+}
+
+/// Finds `impl Trait` in a type.
+struct ImplTraitFinder(Option<Span>);
+
+impl<'ast> Visit<'ast> for ImplTraitFinder {
+    fn visit_type_impl_trait(&mut self, node: &'ast TypeImplTrait) {
+        self.0.get_or_insert(node.impl_token.span);
+    }
+}
+
+/// Collects the bindings of a pattern.
+struct Bindings(Vec<PatIdent>);
+
+impl<'ast> Visit<'ast> for Bindings {
+    fn visit_pat_ident(&mut self, node: &'ast PatIdent) {
+        // uppercase identifiers without modifiers are constants or unit structs and variants
+        if node.by_ref.is_some()
+            || node.mutability.is_some()
+            || node.subpat.is_some()
+            || !node.ident.to_string().starts_with(char::is_uppercase)
+        {
+            // alternatives of or-patterns bind the same names
+            if !self.0.iter().any(|binding| binding.ident == node.ident) {
+                self.0.push(node.clone());
+            }
+        }
+        visit::visit_pat_ident(self, node);
+    }
+}
+
+/// Removes `mut` from bindings by value, which are moved out of the pattern.
+struct RemoveMut;
+
+impl VisitMut for RemoveMut {
+    fn visit_pat_ident_mut(&mut self, node: &mut PatIdent) {
+        if node.by_ref.is_none() {
+            node.mutability = None;
+        }
+        visit_mut::visit_pat_ident_mut(self, node);
+    }
+}
+
+/// Rewrites `let PAT = EXPR else { .. };` into `let (a, b) = match EXPR { PAT => (a, b), _ => { .. } };`.
+fn lower_let_else(local: &mut Local) {
+    let Some(init) = &mut local.init else {
+        return;
+    };
+    let Some((_, diverge)) = init.diverge.take() else {
+        return;
+    };
+    let span = local.let_token.span;
+    let mut bindings = Bindings(vec![]);
+    bindings.visit_pat(&local.pat);
+    let outer: Vec<Pat> = bindings
+        .0
+        .iter()
+        .map(|binding| {
+            Pat::Ident(PatIdent {
+                attrs: vec![],
+                by_ref: None,
+                mutability: binding.mutability.filter(|_| binding.by_ref.is_none()),
+                ident: binding.ident.clone(),
+                subpat: None,
+            })
+        })
+        .collect();
+    let values: Vec<&Ident> = bindings.0.iter().map(|binding| &binding.ident).collect();
+    let mut pat = local.pat.clone();
+    RemoveMut.visit_pat_mut(&mut pat);
+    let expr = &init.expr;
+    let (outer, value): (Pat, Expr) = match (&outer[..], &values[..]) {
+        ([outer], [value]) => (outer.clone(), parse_quote_spanned!(span=> #value)),
+        _ => (
+            parse_quote_spanned!(span=> (#(#outer),*)),
+            parse_quote_spanned!(span=> (#(#values),*)),
+        ),
+    };
+    local.pat = outer;
+    *init.expr = parse_quote_spanned!(span=> match #expr { #pat => #value, _ => #diverge });
+}
+
+/// Splits `a && b && c` into its operands with the operators before them.
+fn chain_operands(expr: Expr, operands: &mut Vec<(Option<BinOp>, Expr)>) {
+    match expr {
+        Expr::Binary(ExprBinary {
+            left,
+            op: op @ BinOp::And(_),
+            right,
+            ..
+        }) => {
+            chain_operands(*left, operands);
+            let start = operands.len();
+            chain_operands(*right, operands);
+            operands[start].0 = Some(op);
+        }
+        expr => operands.push((None, expr)),
+    }
+}
+
+/// Returns whether `cond` is a chain of `&&` with a `let`.
+fn is_let_chain(cond: &Expr) -> bool {
+    match cond {
+        Expr::Binary(ExprBinary {
+            left,
+            op: BinOp::And(_),
+            right,
+            ..
+        }) => {
+            matches!(**left, Expr::Let(_))
+                || matches!(**right, Expr::Let(_))
+                || is_let_chain(left)
+                || is_let_chain(right)
+        }
+        _ => false,
+    }
+}
+
+/// Splits the condition of a let chain into the conditions of nested `if`, keeping consecutive
+/// operands without `let` together.
+fn chain_conds(cond: Expr) -> Vec<Expr> {
+    let mut operands = vec![];
+    chain_operands(cond, &mut operands);
+    let mut conds: Vec<Expr> = vec![];
+    for (op, operand) in operands {
+        match (conds.last_mut(), op) {
+            (Some(last), Some(op))
+                if !matches!(last, Expr::Let(_)) && !matches!(operand, Expr::Let(_)) =>
+            {
+                *last = Expr::Binary(ExprBinary {
+                    attrs: vec![],
+                    left: Box::new(last.clone()),
+                    op,
+                    right: Box::new(operand),
+                });
+            }
+            _ => conds.push(operand),
+        }
+    }
+    conds
+}
+
+/// Nests an `if` for each of `conds` around `then_branch`, repeating `else_branch` for each.
+fn nest_ifs(
+    conds: Vec<Expr>,
+    if_token: Token![if],
+    mut then_branch: Block,
+    else_branch: Option<(Token![else], Box<Expr>)>,
+) -> ExprIf {
+    let brace_token = then_branch.brace_token;
+    let mut conds = conds.into_iter().rev();
+    let mut expr_if = ExprIf {
+        attrs: vec![],
+        if_token,
+        cond: Box::new(conds.next().expect("a let chain has operands")),
+        then_branch,
+        else_branch: else_branch.clone(),
+    };
+    for cond in conds {
+        then_branch = Block {
+            brace_token,
+            stmts: vec![Stmt::Expr(Expr::If(expr_if), None)],
+        };
+        expr_if = ExprIf {
+            attrs: vec![],
+            if_token,
+            cond: Box::new(cond),
+            then_branch,
+            else_branch: else_branch.clone(),
+        };
+    }
+    expr_if
+}
+
+/// Rewrites `if let A = a && b && let C = c { .. }` into nested `if`.
+///
+/// An `else` branch repeated in the nested `if` would see the bindings of the chain, so
+/// `if let A = a && b { x } else { y }` becomes
+/// `match if let A = a { if b { Some({ x }) } else { None } } else { None } { Some(v) => v, None => { y } }`.
+fn lower_if_chain(node: ExprIf) -> Expr {
+    let mut taken = FxHashSet::default();
+    collect_token_idents(node.to_token_stream(), &mut taken);
+    let conds = chain_conds(*node.cond);
+    let Some((else_token, else_branch)) = node.else_branch else {
+        let mut expr_if = nest_ifs(conds, node.if_token, node.then_branch, None);
+        expr_if.attrs = node.attrs;
+        return Expr::If(expr_if);
+    };
+    let span = node.if_token.span;
+    let value = ShortNames::new(ShortNames::LOWER, ShortNames::ALNUM)
+        .find(|name| !taken.contains(name))
+        .map(|name| Ident::new(&name, span))
+        .unwrap();
+    let then_branch = node.then_branch;
+    let expr_if = nest_ifs(
+        conds,
+        node.if_token,
+        parse_quote_spanned!(span=> { Some(#then_branch) }),
+        Some((else_token, parse_quote_spanned!(span=> { None }))),
+    );
+    let mut expr: Expr = parse_quote_spanned!(span=>
+        match #expr_if { Some(#value) => #value, None => #else_branch }
+    );
+    if let Expr::Match(expr_match) = &mut expr {
+        expr_match.attrs = node.attrs;
+    }
+    expr
+}
+
+/// Rewrites `while let A = a && b { .. }` into `loop { if let A = a { if b { .. } else { break } } else { break } }`.
+fn lower_while_chain(node: ExprWhile) -> Expr {
+    let span = node.while_token.span;
+    let expr_if = nest_ifs(
+        chain_conds(*node.cond),
+        Token![if](span),
+        node.body.clone(),
+        Some((Token![else](span), parse_quote_spanned!(span=> { break }))),
+    );
+    Expr::Loop(ExprLoop {
+        attrs: node.attrs,
+        label: node.label,
+        loop_token: Token![loop](span),
+        body: Block {
+            brace_token: node.body.brace_token,
+            stmts: vec![Stmt::Expr(Expr::If(expr_if), None)],
+        },
+    })
+}
+
+/// Passes the identifiers captured by the format string in `tokens` as named arguments, like
+/// `x = x`.
+fn lower_format(name: &str, tokens: TokenStream, edition: Edition) -> TokenStream {
+    let (Some(index), Some(args)) = (format_string_index(name), parse_args(tokens.clone())) else {
+        return tokens;
+    };
+    let Some(Expr::Lit(ExprLit {
+        lit: Lit::Str(fmt), ..
+    })) = args.iter().nth(index)
+    else {
+        return tokens;
+    };
+    if edition < Edition::E2021 && PANIC_MACROS.contains(&name) && args.len() == index + 1 {
+        return tokens;
+    }
+    let Some(format_args) = parse_format(&fmt.value()) else {
+        return tokens;
+    };
+    let named: FxHashSet<String> = args
+        .iter()
+        .skip(index + 1)
+        .filter_map(|arg| match arg {
+            Expr::Assign(assign) => match &*assign.left {
+                Expr::Path(path) => path.path.get_ident().map(Ident::to_string),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    let mut captured: Vec<String> = vec![];
+    for arg in format_args {
+        if let ArgRef::Name(name) = arg.arg {
+            if !named.contains(&name) && !captured.contains(&name) {
+                captured.push(name);
+            }
+        }
+    }
+    // the new tokens are placed at the last argument to keep the range of the macro
+    let Some(span) = tokens.clone().into_iter().last().map(|tt| tt.span()) else {
+        return tokens;
+    };
+    if captured.is_empty() {
+        return tokens;
+    }
+    let idents: Vec<Ident> = captured.iter().map(|name| Ident::new(name, span)).collect();
+    let mut tokens = tokens;
+    if !args.trailing_punct() {
+        tokens.extend(quote_spanned!(span=> ,));
+    }
+    tokens.extend(quote_spanned!(span=> #(#idents = #idents),*));
+    tokens
+}
+
+impl VisitMut for Lowerer {
+    fn visit_expr_mut(&mut self, node: &mut Expr) {
+        if node.get_attributes().is_some_and(is_minify_skip) {
+            return;
+        }
+        visit_mut::visit_expr_mut(self, node);
+        if self.version >= LET_CHAINS {
+            return;
+        }
+        match node {
+            Expr::If(expr_if) if is_let_chain(&expr_if.cond) => {
+                let Expr::If(expr_if) = replace(node, Expr::Verbatim(TokenStream::new())) else {
+                    unreachable!()
+                };
+                *node = lower_if_chain(expr_if);
+            }
+            Expr::While(expr_while) if is_let_chain(&expr_while.cond) => {
+                let Expr::While(expr_while) = replace(node, Expr::Verbatim(TokenStream::new()))
+                else {
+                    unreachable!()
+                };
+                *node = lower_while_chain(expr_while);
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_impl_item_mut(&mut self, node: &mut ImplItem) {
+        if node.get_attributes().is_some_and(is_minify_skip) {
+            return;
+        }
+        match node {
+            ImplItem::Fn(item) if self.trait_impl => {
+                self.check_method(item.sig.asyncness, &item.sig.output);
+            }
+            ImplItem::Type(item) if !item.generics.params.is_empty() => {
+                self.require(
+                    &item.generics,
+                    "a generic associated type",
+                    GENERIC_ASSOCIATED_TYPES,
+                );
+            }
+            _ => {}
+        }
+        visit_mut::visit_impl_item_mut(self, node);
+    }
+
+    fn visit_item_mut(&mut self, node: &mut Item) {
+        if !node.get_attributes().is_some_and(is_minify_skip) {
+            visit_mut::visit_item_mut(self, node);
+        }
+    }
+
+    fn visit_item_impl_mut(&mut self, node: &mut ItemImpl) {
+        let trait_impl = self.trait_impl;
+        self.trait_impl = node.trait_.is_some();
+        visit_mut::visit_item_impl_mut(self, node);
+        self.trait_impl = trait_impl;
+    }
+
+    fn visit_local_mut(&mut self, node: &mut Local) {
+        if is_minify_skip(&node.attrs) {
+            return;
+        }
+        visit_mut::visit_local_mut(self, node);
+        if node
+            .init
+            .as_ref()
+            .is_some_and(|init| init.diverge.is_some())
+            && self.version < LET_ELSE
+        {
+            if let Pat::Type(pat) = &node.pat {
+                self.require(&pat.ty, "`let`-`else` with a type annotation", LET_ELSE);
+            } else {
+                lower_let_else(node);
+            }
+        }
+    }
+
+    fn visit_macro_mut(&mut self, node: &mut Macro) {
+        if self.version < INLINE_FORMAT_ARGS {
+            let edition = self.edition;
+            rewrite_macro(node, &mut |name, tokens| {
+                lower_format(name, tokens, edition)
+            });
+        }
+        visit_mut::visit_macro_mut(self, node);
+    }
+
+    fn visit_stmt_macro_mut(&mut self, node: &mut StmtMacro) {
+        if !is_minify_skip(&node.attrs) {
+            visit_mut::visit_stmt_macro_mut(self, node);
+        }
+    }
+
+    fn visit_trait_item_mut(&mut self, node: &mut TraitItem) {
+        if node.get_attributes().is_some_and(is_minify_skip) {
+            return;
+        }
+        match node {
+            TraitItem::Fn(item) => self.check_method(item.sig.asyncness, &item.sig.output),
+            TraitItem::Type(item) if !item.generics.params.is_empty() => {
+                self.require(
+                    &item.generics,
+                    "a generic associated type",
+                    GENERIC_ASSOCIATED_TYPES,
+                );
+            }
+            _ => {}
+        }
+        visit_mut::visit_trait_item_mut(self, node);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_file;
+    use test_case::test_case;
+
+    #[test_case("1.42", Ok(RustVersion::new(1, 42)))]
+    #[test_case("1.42.1", Ok(RustVersion::new(1, 42)))]
+    #[test_case("1", Err("invalid Rust version `1`".into()))]
+    #[test_case("1.x", Err("invalid Rust version `1.x`".into()))]
+    fn test_rust_version(s: &str, expected: Result<RustVersion, String>) {
+        assert_eq!(s.parse(), expected);
+    }
+
+    #[test_case(
+        "fn f() { let Some(x) = a else { return }; let (Ok(mut y) | Err(mut y), ref z, None) = b else { panic!() }; let A = c else { return }; }",
+        "fn f () { let x = match a { Some (x) => x , _ => { return } } ; let (mut y , z) = match b { (Ok (y) | Err (y) , ref z , None) => (y , z) , _ => { panic ! () } } ; let () = match c { A => () , _ => { return } } ; }";
+        "let else"
+    )]
+    #[test_case(
+        "fn f() { if let Some(x) = a && b && c && let Some(y) = x { 1 } else if d && let Ok(z) = e { 2 } else { 3 }; }",
+        "fn f () { match if let Some (x) = a { if b && c { if let Some (y) = x { Some ({ 1 }) } else { None } } else { None } } else { None } { Some (f) => f , None => match if d { if let Ok (z) = e { Some ({ 2 }) } else { None } } else { None } { Some (a) => a , None => { 3 } } } ; }";
+        "if let chain"
+    )]
+    #[test_case(
+        "fn f(x: u8) -> u8 { if let Some(x) = g() && x > 1 { x } else { x } }",
+        "fn f (x : u8) -> u8 { match if let Some (x) = g () { if x > 1 { Some ({ x }) } else { None } } else { None } { Some (a) => a , None => { x } } }";
+        "if let chain with shadowed else"
+    )]
+    #[test_case(
+        "fn f() { if let Some(x) = a && x > 1 { g(x); } }",
+        "fn f () { if let Some (x) = a { if x > 1 { g (x) ; } } }";
+        "if let chain without else"
+    )]
+    #[test_case(
+        "fn f() { 'a: while let Some(x) = a.pop() && x > 0 { continue 'a; } }",
+        "fn f () { 'a : loop { if let Some (x) = a . pop () { if x > 0 { continue 'a ; } else { break } } else { break } } }";
+        "while let chain"
+    )]
+    #[test_case(
+        "fn f() { println!(\"{x} {y:w$} {}\", 1, y = 2); m!(write!(f, \"{x}\",)); assert!(a, \"{x}\"); }",
+        "fn f () { println ! (\"{x} {y:w$} {}\" , 1 , y = 2 , x = x , w = w) ; m ! (write ! (f , \"{x}\" , x = x)) ; assert ! (a , \"{x}\" , x = x) ; }";
+        "format args"
+    )]
+    #[test_case(
+        "#[rust_minify::skip] fn f() { let Some(x) = a else { return }; } fn g() { #[rust_minify::skip] println!(\"{x}\"); }",
+        "# [rust_minify :: skip] fn f () { let Some (x) = a else { return } ; } fn g () { # [rust_minify :: skip] println ! (\"{x}\") ; }";
+        "skipped"
+    )]
+    fn test_lower(content: &str, expected: &str) {
+        let mut file = parse_file(content).unwrap();
+        lower(&mut file, RustVersion::new(1, 42), Edition::E2021).unwrap();
+        assert_eq!(file.to_token_stream().to_string(), expected);
+    }
+
+    #[test]
+    fn test_lower_panic_2018() {
+        let mut file = parse_file("fn f() { panic!(\"{x}\"); panic!(\"{x}{}\", 1); }").unwrap();
+        lower(&mut file, RustVersion::new(1, 42), Edition::E2018).unwrap();
+        assert_eq!(
+            file.to_token_stream().to_string(),
+            "fn f () { panic ! (\"{x}\") ; panic ! (\"{x}{}\" , 1 , x = x) ; }"
+        );
+    }
+
+    #[test_case(
+        "trait T { fn f() -> impl Copy; async fn g(); type A<'a>; } fn f() { let Some(x): Option<u8> = a else { return }; }",
+        RustVersion::new(1, 64),
+        vec![
+            "`impl Trait` returned from a trait method requires Rust 1.75",
+            "`async fn` in a trait requires Rust 1.75",
+            "a generic associated type requires Rust 1.65",
+            "`let`-`else` with a type annotation requires Rust 1.65",
+        ];
+        "1.64"
+    )]
+    #[test_case(
+        "trait T { fn f() -> impl Copy; } impl T for () { fn f() -> impl Copy {} } impl S { fn f() -> impl Copy {} }",
+        RustVersion::new(1, 74),
+        vec![
+            "`impl Trait` returned from a trait method requires Rust 1.75",
+            "`impl Trait` returned from a trait method requires Rust 1.75",
+        ];
+        "1.74"
+    )]
+    fn test_lower_error(content: &str, version: RustVersion, expected: Vec<&str>) {
+        let mut file = parse_file(content).unwrap();
+        let err = lower(&mut file, version, Edition::E2021).unwrap_err();
+        let messages: Vec<_> = err.into_iter().map(|err| err.to_string()).collect();
+        assert_eq!(messages, expected);
+    }
+}
@@ -0,0 +1,379 @@
+use proc_macro2::{TokenStream, TokenTree};
+use quote::ToTokens;
+use std::{cell::RefCell, str::FromStr};
+use syn::{
+    parse::{ParseStream, Parser},
+    punctuated::Punctuated,
+    visit::Visit,
+    visit_mut::VisitMut,
+    Block, Expr, Item, Macro, Pat, PatType, Path, Stmt, Token, Type,
+};
+
+/// The grammar of the arguments of a macro, by which they are emitted like other code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MacroGrammar {
+    /// Comma-separated expressions, like `assert_eq!` and `println!`.
+    Exprs,
+    /// Comma-separated expressions or `expr; len`, like `vec!`.
+    Vec,
+    /// An expression and a pattern with an optional guard, like `matches!`.
+    Matches,
+    /// Items.
+    Items,
+    /// Statements.
+    Stmts,
+    /// Comma-separated types.
+    Types,
+    /// Comma-separated `pattern: Type`, like `input!` of proconio.
+    TypedPats,
+}
+
+impl FromStr for MacroGrammar {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exprs" => Ok(Self::Exprs),
+            "vec" => Ok(Self::Vec),
+            "matches" => Ok(Self::Matches),
+            "items" => Ok(Self::Items),
+            "stmts" => Ok(Self::Stmts),
+            "types" => Ok(Self::Types),
+            "typed_pats" => Ok(Self::TypedPats),
+            _ => Err(format!("unknown macro grammar `{}`", s)),
+        }
+    }
+}
+
+/// Macros of the standard library whose arguments are parsed.
+const STD_MACROS: [(&str, MacroGrammar); 21] = [
+    ("assert", MacroGrammar::Exprs),
+    ("assert_eq", MacroGrammar::Exprs),
+    ("assert_ne", MacroGrammar::Exprs),
+    ("dbg", MacroGrammar::Exprs),
+    ("debug_assert", MacroGrammar::Exprs),
+    ("debug_assert_eq", MacroGrammar::Exprs),
+    ("debug_assert_ne", MacroGrammar::Exprs),
+    ("eprint", MacroGrammar::Exprs),
+    ("eprintln", MacroGrammar::Exprs),
+    ("format", MacroGrammar::Exprs),
+    ("format_args", MacroGrammar::Exprs),
+    ("matches", MacroGrammar::Matches),
+    ("panic", MacroGrammar::Exprs),
+    ("print", MacroGrammar::Exprs),
+    ("println", MacroGrammar::Exprs),
+    ("todo", MacroGrammar::Exprs),
+    ("unimplemented", MacroGrammar::Exprs),
+    ("unreachable", MacroGrammar::Exprs),
+    ("vec", MacroGrammar::Vec),
+    ("write", MacroGrammar::Exprs),
+    ("writeln", MacroGrammar::Exprs),
+];
+
+thread_local! {
+    /// Grammars registered by [`with_macro_grammars`], which take precedence over `STD_MACROS`.
+    static MACRO_GRAMMARS: RefCell<Vec<(String, MacroGrammar)>> = const { RefCell::new(Vec::new()) };
+    /// Names registered by [`with_local_macros`], which shadow the macros of the standard library.
+    static LOCAL_MACROS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Calls `f` with the macros named in `grammars` parsed by their grammars on this thread.
+pub fn with_macro_grammars<R>(grammars: &[(String, MacroGrammar)], f: impl FnOnce() -> R) -> R {
+    struct Restore(usize);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            MACRO_GRAMMARS.with(|g| g.borrow_mut().truncate(self.0));
+        }
+    }
+    let _restore = Restore(MACRO_GRAMMARS.with(|g| {
+        let mut g = g.borrow_mut();
+        let len = g.len();
+        g.extend_from_slice(grammars);
+        len
+    }));
+    f()
+}
+
+/// Calls `f` with the macros named in `names` defined by the code on this thread, so that
+/// unqualified invocations of them are not taken for the macros of the standard library.
+pub fn with_local_macros<R>(names: &[String], f: impl FnOnce() -> R) -> R {
+    struct Restore(usize);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            LOCAL_MACROS.with(|m| m.borrow_mut().truncate(self.0));
+        }
+    }
+    let _restore = Restore(LOCAL_MACROS.with(|m| {
+        let mut m = m.borrow_mut();
+        let len = m.len();
+        m.extend_from_slice(names);
+        len
+    }));
+    f()
+}
+
+/// Returns `true` if `name` is registered by [`with_local_macros`].
+pub fn is_local_macro(name: &str) -> bool {
+    LOCAL_MACROS.with(|m| m.borrow().iter().any(|n| n == name))
+}
+
+/// Collects the names defined by `macro_rules!` in `tokens`.
+pub fn collect_macro_rules(tokens: TokenStream, names: &mut Vec<String>) {
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(tt) = tokens.next() {
+        if let TokenTree::Group(group) = &tt {
+            collect_macro_rules(group.stream(), names);
+            continue;
+        }
+        let is_macro_rules = matches!(&tt, TokenTree::Ident(ident) if ident == "macro_rules")
+            && tokens
+                .next_if(|tt| matches!(tt, TokenTree::Punct(p) if p.as_char() == '!'))
+                .is_some();
+        if let (true, Some(TokenTree::Ident(name))) = (is_macro_rules, tokens.peek()) {
+            names.push(name.to_string());
+        }
+    }
+}
+
+/// Returns the name of the macro of the standard library `path` may refer to: the last segment
+/// of a path starting with `std`, `core` or `alloc`, or a single name not defined by the code.
+pub fn std_macro_name(path: &Path) -> Option<String> {
+    let name = path.segments.last()?.ident.to_string();
+    if path.segments.len() == 1 && path.leading_colon.is_none() {
+        return (!is_local_macro(&name)).then_some(name);
+    }
+    let first = &path.segments.first()?.ident;
+    (first == "std" || first == "core" || first == "alloc").then_some(name)
+}
+
+/// Returns the grammar of the arguments of `mac`, registered for the last segment of its path or
+/// known for the macro of the standard library it refers to.
+pub fn macro_grammar(mac: &Macro) -> Option<MacroGrammar> {
+    let name = mac.path.segments.last()?.ident.to_string();
+    MACRO_GRAMMARS
+        .with(|g| {
+            g.borrow()
+                .iter()
+                .rev()
+                .find(|(n, _)| *n == name)
+                .map(|&(_, grammar)| grammar)
+        })
+        .or_else(|| {
+            let name = std_macro_name(&mac.path)?;
+            STD_MACROS
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|&(_, grammar)| grammar)
+        })
+}
+
+/// The arguments of a macro parsed by its [`MacroGrammar`].
+#[derive(Debug, Clone)]
+pub enum MacroArgs {
+    Exprs(Punctuated<Expr, Token![,]>),
+    Repeat(Box<Expr>, Token![;], Box<Expr>),
+    Matches {
+        expr: Box<Expr>,
+        comma: Token![,],
+        pat: Box<Pat>,
+        guard: Option<(Token![if], Box<Expr>)>,
+        trailing: Option<Token![,]>,
+    },
+    Items(Vec<Item>),
+    Stmts(Vec<Stmt>),
+    Types(Punctuated<Type, Token![,]>),
+    TypedPats(Punctuated<PatType, Token![,]>),
+}
+
+fn parse_typed_pat(input: ParseStream) -> syn::Result<PatType> {
+    Ok(PatType {
+        attrs: vec![],
+        pat: Box::new(Pat::parse_single(input)?),
+        colon_token: input.parse()?,
+        ty: input.parse()?,
+    })
+}
+
+fn parse_args(input: ParseStream, grammar: MacroGrammar) -> syn::Result<MacroArgs> {
+    Ok(match grammar {
+        MacroGrammar::Exprs => MacroArgs::Exprs(Punctuated::parse_terminated(input)?),
+        MacroGrammar::Vec => {
+            let fork = input.fork();
+            if fork.parse::<Expr>().is_ok() && fork.peek(Token![;]) {
+                MacroArgs::Repeat(input.parse()?, input.parse()?, input.parse()?)
+            } else {
+                MacroArgs::Exprs(Punctuated::parse_terminated(input)?)
+            }
+        }
+        MacroGrammar::Matches => MacroArgs::Matches {
+            expr: input.parse()?,
+            comma: input.parse()?,
+            pat: Box::new(Pat::parse_multi_with_leading_vert(input)?),
+            guard: if input.peek(Token![if]) {
+                Some((input.parse()?, input.parse()?))
+            } else {
+                None
+            },
+            trailing: input.parse()?,
+        },
+        MacroGrammar::Items => {
+            let mut items = vec![];
+            while !input.is_empty() {
+                items.push(input.parse()?);
+            }
+            MacroArgs::Items(items)
+        }
+        MacroGrammar::Stmts => MacroArgs::Stmts(Block::parse_within(input)?),
+        MacroGrammar::Types => MacroArgs::Types(Punctuated::parse_terminated(input)?),
+        MacroGrammar::TypedPats => {
+            MacroArgs::TypedPats(Punctuated::parse_terminated_with(input, parse_typed_pat)?)
+        }
+    })
+}
+
+impl MacroArgs {
+    /// Parses the arguments of `mac`, or returns `None` if its grammar is unknown or they do not
+    /// match it.
+    pub fn parse(mac: &Macro) -> Option<Self> {
+        let grammar = macro_grammar(mac)?;
+        (|input: ParseStream| parse_args(input, grammar))
+            .parse2(mac.tokens.clone())
+            .ok()
+    }
+
+    pub fn visit<'ast, V: Visit<'ast> + ?Sized>(&'ast self, v: &mut V) {
+        match self {
+            Self::Exprs(exprs) => exprs.iter().for_each(|expr| v.visit_expr(expr)),
+            Self::Repeat(expr, _, len) => {
+                v.visit_expr(expr);
+                v.visit_expr(len);
+            }
+            Self::Matches {
+                expr, pat, guard, ..
+            } => {
+                v.visit_expr(expr);
+                v.visit_pat(pat);
+                if let Some((_, guard)) = guard {
+                    v.visit_expr(guard);
+                }
+            }
+            Self::Items(items) => items.iter().for_each(|item| v.visit_item(item)),
+            Self::Stmts(stmts) => stmts.iter().for_each(|stmt| v.visit_stmt(stmt)),
+            Self::Types(types) => types.iter().for_each(|ty| v.visit_type(ty)),
+            Self::TypedPats(pats) => pats.iter().for_each(|pat| v.visit_pat_type(pat)),
+        }
+    }
+
+    pub fn visit_mut<V: VisitMut + ?Sized>(&mut self, v: &mut V) {
+        match self {
+            Self::Exprs(exprs) => exprs.iter_mut().for_each(|expr| v.visit_expr_mut(expr)),
+            Self::Repeat(expr, _, len) => {
+                v.visit_expr_mut(expr);
+                v.visit_expr_mut(len);
+            }
+            Self::Matches {
+                expr, pat, guard, ..
+            } => {
+                v.visit_expr_mut(expr);
+                v.visit_pat_mut(pat);
+                if let Some((_, guard)) = guard {
+                    v.visit_expr_mut(guard);
+                }
+            }
+            Self::Items(items) => items.iter_mut().for_each(|item| v.visit_item_mut(item)),
+            Self::Stmts(stmts) => stmts.iter_mut().for_each(|stmt| v.visit_stmt_mut(stmt)),
+            Self::Types(types) => types.iter_mut().for_each(|ty| v.visit_type_mut(ty)),
+            Self::TypedPats(pats) => pats.iter_mut().for_each(|pat| v.visit_pat_type_mut(pat)),
+        }
+    }
+}
+
+impl ToTokens for MacroArgs {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        match self {
+            Self::Exprs(exprs) => exprs.to_tokens(tokens),
+            Self::Repeat(expr, semi, len) => {
+                expr.to_tokens(tokens);
+                semi.to_tokens(tokens);
+                len.to_tokens(tokens);
+            }
+            Self::Matches {
+                expr,
+                comma,
+                pat,
+                guard,
+                trailing,
+            } => {
+                expr.to_tokens(tokens);
+                comma.to_tokens(tokens);
+                pat.to_tokens(tokens);
+                if let Some((if_token, guard)) = guard {
+                    if_token.to_tokens(tokens);
+                    guard.to_tokens(tokens);
+                }
+                trailing.to_tokens(tokens);
+            }
+            Self::Items(items) => tokens.extend(items.iter().map(ToTokens::to_token_stream)),
+            Self::Stmts(stmts) => tokens.extend(stmts.iter().map(ToTokens::to_token_stream)),
+            Self::Types(types) => types.to_tokens(tokens),
+            Self::TypedPats(pats) => pats.to_tokens(tokens),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case("vec![1, 2,]", Some("1 , 2 ,"); "exprs")]
+    #[test_case("vec![0; n]", Some("0 ; n"); "repeat")]
+    #[test_case("matches!(x, | Some(_) | None if y,)", Some("x , | Some (_) | None if y ,"); "matches")]
+    #[test_case("input!{ n: usize, mut a: [u8; n] }", Some("n : usize , mut a : [u8 ; n]"); "typed pats")]
+    #[test_case("input!{ from r, n: usize }", None; "mismatch")]
+    #[test_case("m!(a b)", None; "unknown")]
+    fn test_macro_args(content: &str, expected: Option<&str>) {
+        let mac: Macro = syn::parse_str(content).unwrap();
+        let grammars = [("input".to_string(), MacroGrammar::TypedPats)];
+        let args = with_macro_grammars(&grammars, || MacroArgs::parse(&mac));
+        assert_eq!(
+            args.map(|args| args.to_token_stream().to_string())
+                .as_deref(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_with_macro_grammars() {
+        let mac: Macro = syn::parse_str("vec![1]").unwrap();
+        assert_eq!(macro_grammar(&mac), Some(MacroGrammar::Vec));
+        let grammars = [("vec".to_string(), MacroGrammar::Types)];
+        with_macro_grammars(&grammars, || {
+            assert_eq!(macro_grammar(&mac), Some(MacroGrammar::Types));
+        });
+        assert_eq!(macro_grammar(&mac), Some(MacroGrammar::Vec));
+    }
+
+    #[test_case("vec![1]", &[], Some(MacroGrammar::Vec); "std")]
+    #[test_case("vec![1]", &["vec"], None; "local")]
+    #[test_case("std::vec![1]", &["vec"], Some(MacroGrammar::Vec); "std path")]
+    #[test_case("alloc::vec![1]", &[], Some(MacroGrammar::Vec); "alloc path")]
+    #[test_case("my::vec![1]", &[], None; "other path")]
+    fn test_with_local_macros(content: &str, local: &[&str], expected: Option<MacroGrammar>) {
+        let mac: Macro = syn::parse_str(content).unwrap();
+        let local: Vec<String> = local.iter().map(|name| name.to_string()).collect();
+        with_local_macros(&local, || assert_eq!(macro_grammar(&mac), expected));
+        assert!(!is_local_macro("vec"));
+    }
+
+    #[test]
+    fn test_collect_macro_rules() {
+        let tokens = TokenStream::from_str(
+            "macro_rules! vec { () => {} } fn f() { macro_rules! write { () => {} } macro_rules!(); }",
+        )
+        .unwrap();
+        let mut names = vec![];
+        collect_macro_rules(tokens, &mut names);
+        assert_eq!(names, ["vec", "write"]);
+    }
+}
@@ -0,0 +1,339 @@
+mod cli;
+
+use rust_minify::{
+    bundle::Bundler, minify_opt, minify_with_source_map, source_map::SourceMap, MinifyOption,
+};
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+fn usage() -> String {
+    format!(
+        "\
+Usage: rust_minify [OPTIONS] [INPUT]
+
+Minify rust code.
+
+Arguments:
+  [INPUT]  Source file or directory; reads stdin if omitted or `-`
+
+Options:
+  -o, --output <PATH>         Write to PATH (a directory if INPUT is a directory)
+  -i, --in-place              Overwrite the input files
+  -b, --bundle                Inline out-of-line `mod` declarations of the INPUT file
+      --extern <NAME=PATH>    Embed the library crate NAME rooted at PATH (implies `--bundle`)
+      --source-map <PATH>     Write a Source Map v3 of the output to PATH
+{}  -h, --help                  Print help
+  -V, --version               Print version
+",
+        cli::options_help()
+    )
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Args {
+    input: Option<PathBuf>,
+    output: Option<PathBuf>,
+    in_place: bool,
+    bundle: bool,
+    externs: Vec<(String, PathBuf)>,
+    source_map: Option<PathBuf>,
+    option: MinifyOption,
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Run(Box<Args>),
+    Help,
+    Version,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut parsed = Args::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-i" | "--in-place" => parsed.in_place = true,
+            "-b" | "--bundle" => parsed.bundle = true,
+            "--extern" => match args.next().as_deref().and_then(|arg| arg.split_once('=')) {
+                Some((name, path)) => {
+                    parsed.bundle = true;
+                    parsed.externs.push((name.to_string(), path.into()));
+                }
+                None => return Err("`--extern` requires a value `NAME=PATH`".to_string()),
+            },
+            "--source-map" => match args.next() {
+                Some(path) => parsed.source_map = Some(path.into()),
+                None => return Err(format!("`{}` requires a value", arg)),
+            },
+            _ if cli::parse_option_arg(&mut parsed.option, &arg, &mut args)? => {}
+            "-o" | "--output" => match args.next() {
+                Some(path) => parsed.output = Some(path.into()),
+                None => return Err(format!("`{}` requires a value", arg)),
+            },
+            "-" if parsed.input.is_none() => parsed.input = Some(arg.into()),
+            _ if arg.starts_with("--output=") => {
+                parsed.output = Some(arg["--output=".len()..].into());
+            }
+            _ if arg.starts_with('-') => return Err(format!("unexpected option `{}`", arg)),
+            _ if parsed.input.is_none() => parsed.input = Some(arg.into()),
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+    if parsed.in_place && parsed.output.is_some() {
+        return Err("`--in-place` cannot be used with `--output`".to_string());
+    }
+    if parsed.bundle && parsed.source_map.is_some() {
+        return Err("`--source-map` cannot be used with `--bundle`".to_string());
+    }
+    Ok(Command::Run(Box::new(parsed)))
+}
+
+fn minify_source(
+    content: &str,
+    path: Option<&Path>,
+    option: &MinifyOption,
+    source_map: bool,
+) -> Result<(String, Option<SourceMap>), String> {
+    let result = if source_map {
+        minify_with_source_map(content, option).map(|(minified, map)| (minified, Some(map)))
+    } else {
+        minify_opt(content, option).map(|minified| (minified, None))
+    };
+    result.map_err(|err| {
+        let start = err.span().start();
+        let name = path.map_or("<stdin>".into(), |path| path.display().to_string());
+        format!("{}:{}:{}: {}", name, start.line, start.column + 1, err)
+    })
+}
+
+fn write_output(path: Option<&Path>, minified: &str) -> Result<(), String> {
+    match path {
+        Some(path) => fs::write(path, minified)
+            .map_err(|err| format!("failed to write `{}`: {}", path.display(), err)),
+        None => io::stdout()
+            .write_all(minified.as_bytes())
+            .map_err(|err| format!("failed to write to stdout: {}", err)),
+    }
+}
+
+fn write_source_map(
+    path: &Path,
+    map: &SourceMap,
+    input: Option<&Path>,
+    output: Option<&Path>,
+) -> Result<(), String> {
+    let file = output
+        .and_then(Path::file_name)
+        .map(|name| name.to_string_lossy());
+    let source = input.map_or("<stdin>".into(), |input| input.display().to_string());
+    fs::write(path, map.to_json(file.as_deref(), &source))
+        .map_err(|err| format!("failed to write `{}`: {}", path.display(), err))
+}
+
+fn run_file(
+    input: &Path,
+    output: Option<&Path>,
+    option: &MinifyOption,
+    source_map: Option<&Path>,
+) -> Result<(), String> {
+    let content = fs::read_to_string(input)
+        .map_err(|err| format!("failed to read `{}`: {}", input.display(), err))?;
+    let (minified, map) = minify_source(&content, Some(input), option, source_map.is_some())?;
+    if let (Some(path), Some(map)) = (source_map, map) {
+        write_source_map(path, &map, Some(input), output)?;
+    }
+    write_output(output, &minified)
+}
+
+fn collect_rust_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries =
+        fs::read_dir(dir).map_err(|err| format!("failed to read `{}`: {}", dir.display(), err))?;
+    for entry in entries {
+        let path = entry
+            .map_err(|err| format!("failed to read `{}`: {}", dir.display(), err))?
+            .path();
+        if path.is_dir() {
+            collect_rust_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn run_dir(input: &Path, output: Option<&Path>, option: &MinifyOption) -> Result<(), String> {
+    let mut files = vec![];
+    collect_rust_files(input, &mut files)?;
+    files.sort();
+    for file in files {
+        let dest = match output {
+            Some(output) => {
+                let dest = output.join(file.strip_prefix(input).unwrap());
+                if let Some(parent) = dest.parent() {
+                    fs::create_dir_all(parent).map_err(|err| {
+                        format!("failed to create `{}`: {}", parent.display(), err)
+                    })?;
+                }
+                dest
+            }
+            None => file.clone(),
+        };
+        run_file(&file, Some(&dest), option, None)?;
+    }
+    Ok(())
+}
+
+fn run(args: Args) -> Result<(), String> {
+    match args.input.as_deref() {
+        None => run_stdin(&args),
+        Some(input) if input == Path::new("-") => run_stdin(&args),
+        Some(input) if args.bundle && input.is_dir() => Err(format!(
+            "`--bundle` requires a file but `{}` is a directory",
+            input.display()
+        )),
+        Some(input) if input.is_dir() => {
+            if args.source_map.is_some() {
+                return Err(format!(
+                    "`--source-map` requires a file but `{}` is a directory",
+                    input.display()
+                ));
+            }
+            if !args.in_place && args.output.is_none() {
+                return Err(format!(
+                    "`{}` is a directory; use `--in-place` or `--output <DIR>`",
+                    input.display()
+                ));
+            }
+            run_dir(input, args.output.as_deref(), &args.option)
+        }
+        Some(input) => {
+            let output = if args.in_place {
+                Some(input)
+            } else {
+                args.output.as_deref()
+            };
+            if args.bundle {
+                let bundler = args
+                    .externs
+                    .iter()
+                    .fold(Bundler::new(input), |bundler, (name, path)| {
+                        bundler.dependency(name, path)
+                    });
+                let minified = bundler
+                    .minify(&args.option)
+                    .map_err(|err| err.to_string())?;
+                write_output(output, &minified)
+            } else {
+                run_file(input, output, &args.option, args.source_map.as_deref())
+            }
+        }
+    }
+}
+
+fn run_stdin(args: &Args) -> Result<(), String> {
+    if args.in_place || args.bundle {
+        return Err("`--in-place` and `--bundle` require an input file".to_string());
+    }
+    let mut content = String::new();
+    io::stdin()
+        .read_to_string(&mut content)
+        .map_err(|err| format!("failed to read from stdin: {}", err))?;
+    let source_map = args.source_map.as_deref();
+    let (minified, map) = minify_source(&content, None, &args.option, source_map.is_some())?;
+    if let (Some(path), Some(map)) = (source_map, map) {
+        write_source_map(path, &map, None, args.output.as_deref())?;
+    }
+    write_output(args.output.as_deref(), &minified)
+}
+
+fn main() -> ExitCode {
+    match parse_args(env::args().skip(1)) {
+        Ok(Command::Run(args)) => match run(*args) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                ExitCode::FAILURE
+            }
+        },
+        Ok(Command::Help) => {
+            print!("{}", usage());
+            ExitCode::SUCCESS
+        }
+        Ok(Command::Version) => {
+            println!("rust_minify {}", env!("CARGO_PKG_VERSION"));
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, usage());
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn args(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_usage_aligned() {
+        let usage = usage();
+        let options = usage.split("Options:\n").nth(1).unwrap();
+        // flags too long for the column put their description on the next line
+        for line in options.lines().filter(|line| line.trim().contains("  ")) {
+            assert!(line[..30].ends_with("  "), "{}", line);
+            assert!(!line[30..].starts_with(' '), "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            args(&["--remove-skip", "--add-rustfmt-skip", "a.rs", "-o", "b.rs"]),
+            Ok(Command::Run(Box::new(Args {
+                input: Some("a.rs".into()),
+                output: Some("b.rs".into()),
+                in_place: false,
+                bundle: false,
+                externs: vec![],
+                source_map: None,
+                option: MinifyOption {
+                    remove_skip: true,
+                    add_rustfmt_skip: true,
+                    ..Default::default()
+                },
+            })))
+        );
+        assert!(matches!(
+            args(&["--extern", "lib=../lib/src/lib.rs", "main.rs"]),
+            Ok(Command::Run(args)) if matches!(
+                *args,
+                Args { bundle: true, ref externs, .. }
+                    if externs == &[("lib".to_string(), "../lib/src/lib.rs".into())]
+            )
+        ));
+        assert_eq!(args(&["-V"]), Ok(Command::Version));
+        assert_eq!(args(&[]), Ok(Command::Run(Box::default())));
+    }
+
+    #[test_case(&["--unknown"]; "unknown option")]
+    #[test_case(&["--cfg", "all(a)"]; "invalid cfg")]
+    #[test_case(&["a.rs", "b.rs"]; "multiple inputs")]
+    #[test_case(&["-o"]; "missing output")]
+    #[test_case(&["--extern", "lib"]; "extern without path")]
+    #[test_case(&["-i", "-o", "b.rs", "a.rs"]; "in place with output")]
+    #[test_case(&["--source-map", "a.map", "-b", "a.rs"]; "source map with bundle")]
+    fn test_parse_args_error(arg: &[&str]) {
+        assert!(args(arg).is_err());
+    }
+}
@@ -1,10 +1,13 @@
+use crate::attr::{is_control_meta, SkippedNodes};
+use crate::macros::MacroArgs;
 use fxhash::FxHashSet;
-use proc_macro2::TokenStream;
-use std::{iter::once, ops::Range};
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use std::{fmt, iter::once, ops::Range};
 use syn::{
+    parse2,
     spanned::Spanned,
     visit::{self, Visit},
-    BinOp, Expr, File, ForeignItem, ImplItem, Item, Macro, Pat, TraitItem, Type,
+    Attribute, BinOp, Expr, File, ForeignItem, ImplItem, Item, Macro, Meta, Pat, TraitItem, Type,
 };
 
 /// A line-column pair representing the start or end of a Span.
@@ -32,38 +35,325 @@ impl From<proc_macro2::LineColumn> for LineColumn {
     }
 }
 
+/// An error of [`LinedSource`] for a position which is not in the source.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PositionError {
+    /// [`LineColumn::line`] is 1-indexed, but was 0.
+    LineZero,
+    /// The line or the column is past the end of the source.
+    OutOfRange(LineColumn),
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PositionError::LineZero => write!(f, "LineColumn::line is 1-indexed but 0"),
+            PositionError::OutOfRange(lc) => {
+                write!(f, "{}:{} is out of the source", lc.line, lc.column + 1)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
+
 #[derive(Debug, Clone)]
 pub struct LinedSource<'s> {
     content: &'s str,
+    /// Byte offsets of the line starts.
     lines: Vec<usize>,
+    /// Whether each line is ASCII, where columns are byte offsets.
+    ascii: Vec<bool>,
 }
 impl<'s> LinedSource<'s> {
     pub fn new(content: &'s str) -> Self {
-        let lines = once(0)
+        let lines: Vec<usize> = once(0)
             .chain(
                 content
                     .char_indices()
                     .filter_map(|(i, c)| if c == '\n' { Some(i + 1) } else { None }),
             )
             .collect();
-        Self { content, lines }
+        let ascii = lines
+            .iter()
+            .zip(lines.iter().skip(1).chain(once(&content.len())))
+            .map(|(&start, &end)| content[start..end].is_ascii())
+            .collect();
+        Self {
+            content,
+            lines,
+            ascii,
+        }
     }
-    fn pos(&self, lc: &LineColumn) -> Option<usize> {
-        assert_ne!(lc.line, 0, "LineColumn::line is 1-indexed but {}", lc.line);
-        self.lines.get(lc.line - 1).map(|p| p + lc.column)
+    /// Returns the byte offset of `lc` in the source, whose column counts characters.
+    pub fn pos(&self, lc: &LineColumn) -> Result<usize, PositionError> {
+        if lc.line == 0 {
+            return Err(PositionError::LineZero);
+        }
+        let out_of_range = PositionError::OutOfRange(*lc);
+        let start = *self.lines.get(lc.line - 1).ok_or(out_of_range)?;
+        let end = self
+            .lines
+            .get(lc.line)
+            .copied()
+            .unwrap_or(self.content.len());
+        if self.ascii[lc.line - 1] {
+            return (start + lc.column <= end)
+                .then_some(start + lc.column)
+                .ok_or(out_of_range);
+        }
+        self.content[start..end]
+            .char_indices()
+            .map(|(i, _)| start + i)
+            .chain(once(end))
+            .nth(lc.column)
+            .ok_or(out_of_range)
     }
-    pub fn get(&self, range: &Range<LineColumn>) -> Option<&'s str> {
-        match (self.pos(&range.start), self.pos(&range.end)) {
-            (Some(start), Some(end)) => self.content.get(start..end),
-            _ => None,
+    /// Returns the column of `lc` in UTF-16 code units.
+    pub fn utf16_column(&self, lc: &LineColumn) -> Result<usize, PositionError> {
+        let pos = self.pos(lc)?;
+        if self.ascii[lc.line - 1] {
+            return Ok(lc.column);
         }
+        Ok(self.content[self.lines[lc.line - 1]..pos]
+            .encode_utf16()
+            .count())
+    }
+    pub fn get(&self, range: &Range<LineColumn>) -> Result<&'s str, PositionError> {
+        let start = self.pos(&range.start)?;
+        let end = self.pos(&range.end)?;
+        self.content
+            .get(start..end)
+            .ok_or(PositionError::OutOfRange(range.end))
+    }
+    /// Returns the position of the end of the source.
+    pub fn end(&self) -> LineColumn {
+        let start = self.lines[self.lines.len() - 1];
+        LineColumn::new(self.lines.len(), self.content[start..].chars().count())
+    }
+    /// Returns the lines between `// rust_minify:off` and `// rust_minify:on` comments, each
+    /// alone on its line.
+    ///
+    /// A region without `// rust_minify:on` extends to the end of the source.
+    pub fn skip_regions(&self) -> Vec<SkipRegion<'s>> {
+        let mut regions = vec![];
+        let mut off = None;
+        for comment in self.comments() {
+            let Some(marker) = comment.source.strip_prefix("//").map(str::trim) else {
+                continue;
+            };
+            let line = comment.range.start.line - 1;
+            let alone = self
+                .pos(&comment.range.start)
+                .is_ok_and(|pos| self.content[self.lines[line]..pos].trim().is_empty());
+            match (marker, off) {
+                (SKIP_OFF, None) if alone => off = Some(line + 1),
+                (SKIP_ON, Some(first)) if alone => {
+                    regions.extend(self.skip_region(first..line));
+                    off = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(first) = off {
+            regions.extend(self.skip_region(first..self.lines.len()));
+        }
+        regions
+    }
+    /// Returns the region of the 0-indexed `lines`, if not empty.
+    fn skip_region(&self, lines: Range<usize>) -> Option<SkipRegion<'s>> {
+        if lines.is_empty() {
+            return None;
+        }
+        let start = self.lines[lines.start];
+        let end = self
+            .lines
+            .get(lines.end)
+            .copied()
+            .unwrap_or(self.content.len());
+        let source = self.content[start..end].trim_end_matches(['\n', '\r']);
+        Some(SkipRegion {
+            range: LineColumn::new(lines.start + 1, 0)..LineColumn::new(lines.end + 1, 0),
+            source,
+        })
+    }
+}
+
+impl<'s> LinedSource<'s> {
+    /// Returns the comments of the source, skipping string and character literals.
+    pub fn comments(&self) -> Vec<Comment<'s>> {
+        let content = self.content;
+        let mut chars = content.char_indices().peekable();
+        let mut comments = vec![];
+        let mut lc = LineColumn::new(1, 0);
+        let mut prev = ' ';
+        let advance = |lc: &mut LineColumn, c: char| {
+            if c == '\n' {
+                *lc = LineColumn::new(lc.line + 1, 0);
+            } else {
+                lc.column += 1;
+            }
+        };
+        while let Some((i, c)) = chars.next() {
+            let start = lc;
+            advance(&mut lc, c);
+            let rest = &content[i..];
+            let len = if rest.starts_with("//") {
+                rest.find('\n').unwrap_or(rest.len())
+            } else if rest.starts_with("/*") {
+                block_comment_len(rest)
+            } else if c == '"' {
+                string_len(rest)
+            } else if !is_ident_char(prev)
+                && (c == 'r' || (c == 'b' || c == 'c') && rest[1..].starts_with('r'))
+            {
+                raw_string_len(rest).unwrap_or(1)
+            } else if c == '\'' {
+                char_len(rest).unwrap_or(1)
+            } else {
+                prev = c;
+                continue;
+            };
+            let token = &content[i..i + len];
+            for c in token[c.len_utf8()..].chars() {
+                advance(&mut lc, c);
+            }
+            while chars.next_if(|&(j, _)| j < i + len).is_some() {}
+            if token.starts_with("//") || token.starts_with("/*") {
+                comments.push(Comment {
+                    range: start..lc,
+                    source: token,
+                });
+            }
+            prev = token.chars().next_back().unwrap_or(' ');
+        }
+        comments
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Returns the length of the nested block comment at the start of `s`.
+fn block_comment_len(s: &str) -> usize {
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < s.len() {
+        if s[i..].starts_with("/*") {
+            depth += 1;
+            i += 2;
+        } else if s[i..].starts_with("*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += s[i..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    s.len()
+}
+
+/// Returns the length of the string literal at the start of `s`, which starts with `"`.
+fn string_len(s: &str) -> usize {
+    let mut escaped = false;
+    for (i, c) in s.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return i + 1,
+            _ => {}
+        }
+    }
+    s.len()
+}
+
+/// Returns the length of the raw string literal at the start of `s`, like `r#"…"#` or `br"…"`.
+fn raw_string_len(s: &str) -> Option<usize> {
+    let prefix = s.find('r')? + 1;
+    let hashes = s[prefix..].len() - s[prefix..].trim_start_matches('#').len();
+    let body = prefix + hashes;
+    if !s[body..].starts_with('"') {
+        return None;
+    }
+    let end = "\"".to_string() + &"#".repeat(hashes);
+    Some(
+        s[body + 1..]
+            .find(&end)
+            .map_or(s.len(), |i| body + 1 + i + end.len()),
+    )
+}
+
+/// Returns the length of the character literal at the start of `s`, or `None` for a lifetime.
+fn char_len(s: &str) -> Option<usize> {
+    let mut chars = s.char_indices().skip(1);
+    match chars.next()? {
+        (_, '\\') => {
+            chars.next()?;
+            let (i, _) = chars.find(|&(_, c)| c == '\'')?;
+            Some(i + 1)
+        }
+        _ => match chars.next()? {
+            (i, '\'') => Some(i + 1),
+            _ => None,
+        },
+    }
+}
+
+/// A comment of the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment<'s> {
+    pub range: Range<LineColumn>,
+    pub source: &'s str,
+}
+impl Comment<'_> {
+    /// Returns `true` for doc comments, which are tokenized as `doc` attributes.
+    pub fn is_doc(&self) -> bool {
+        let s = self.source;
+        (s.starts_with("///") && !s.starts_with("////"))
+            || s.starts_with("//!")
+            || (s.starts_with("/**") && !s.starts_with("/***") && s != "/**/")
+            || s.starts_with("/*!")
+    }
+    /// Returns `true` for `/*! */` comments and comments with `@license` or `@preserve`.
+    pub fn is_preserved(&self) -> bool {
+        self.source.starts_with("/*!")
+            || self.source.contains("@license")
+            || self.source.contains("@preserve")
     }
 }
 
+const SKIP_OFF: &str = "rust_minify:off";
+const SKIP_ON: &str = "rust_minify:on";
+
+/// Lines between `// rust_minify:off` and `// rust_minify:on`, which are emitted as they are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkipRegion<'s> {
+    /// From the start of the first line to the start of the line after the last line.
+    pub range: Range<LineColumn>,
+    /// The lines without the last newline.
+    pub source: &'s str,
+}
+
+/// A node with `#[rust_minify::skip]`, which is emitted as its original source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Skipped {
+    pub range: Range<LineColumn>,
+    /// The control attributes in the node, each up to the start of the token following it.
+    pub attrs: Vec<Range<LineColumn>>,
+}
+
 #[derive(Debug, Default)]
 pub struct SpanCollector {
+    /// Whether `#[rust_minify::minify]` is removed from skipped nodes like the skip attribute.
+    pub marker: bool,
     pub bitwise_and: FxHashSet<LineColumn>,
     pub tokens: Vec<Range<LineColumn>>,
+    pub skipped: Vec<Skipped>,
+    /// The starts of the items, before which preserved comments are emitted.
+    pub item_starts: FxHashSet<LineColumn>,
 }
 impl SpanCollector {
     pub fn new() -> Self {
@@ -72,12 +362,20 @@ impl SpanCollector {
     pub fn clear(&mut self) {
         self.bitwise_and.clear();
         self.tokens.clear();
+        self.skipped.clear();
+        self.item_starts.clear();
     }
     pub fn collect(&mut self, file: &File) {
         self.visit_file(file);
+        let marker = self.marker;
+        SkippedNodes(|tokens, _: &Attribute| self.skipped.push(Skipped::new(tokens, marker)))
+            .visit_file(file);
     }
     pub fn collect_item(&mut self, item: &Item) {
         self.visit_item(item);
+        let marker = self.marker;
+        SkippedNodes(|tokens, _: &Attribute| self.skipped.push(Skipped::new(tokens, marker)))
+            .visit_item(item);
     }
     fn visit_token_stream(&mut self, tokens: &TokenStream) {
         if !tokens.is_empty() {
@@ -86,6 +384,83 @@ impl SpanCollector {
         }
     }
 }
+impl Skipped {
+    /// Returns the node `tokens`, such as a node with the skip attribute or an unmarked item in
+    /// the opt-in mode, in which the control attributes are found as [`is_control_meta`] with
+    /// `marker`.
+    pub fn new(tokens: TokenStream, marker: bool) -> Self {
+        let span = tokens.span();
+        let range: Range<LineColumn> = span.start().into()..span.end().into();
+        let mut attrs = vec![];
+        control_attrs(tokens, range.end, marker, &mut attrs);
+        Self { range, attrs }
+    }
+    /// Returns the original source with the positions of its pieces, without the control
+    /// attributes if `remove_attr` is `true`.
+    pub fn pieces<'s>(
+        &self,
+        source: &LinedSource<'s>,
+        remove_attr: bool,
+    ) -> Result<Vec<(LineColumn, &'s str)>, PositionError> {
+        let range = &self.range;
+        if !remove_attr {
+            return Ok(vec![(range.start, source.get(range)?)]);
+        }
+        let mut pieces = vec![];
+        let mut start = range.start;
+        for attr in &self.attrs {
+            if start < attr.start {
+                pieces.push((start, source.get(&(start..attr.start))?));
+            }
+            start = attr.end;
+        }
+        if start < range.end {
+            pieces.push((start, source.get(&(start..range.end))?));
+        }
+        Ok(pieces)
+    }
+}
+/// Collects the ranges of the control attributes in `tokens`, each up to the start of the token
+/// following it or `end`, also inside groups for inner attributes.
+fn control_attrs(
+    tokens: TokenStream,
+    end: LineColumn,
+    marker: bool,
+    attrs: &mut Vec<Range<LineColumn>>,
+) {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i] {
+            TokenTree::Punct(pound) if pound.as_char() == '#' => {
+                let bang =
+                    matches!(tokens.get(i + 1), Some(TokenTree::Punct(p)) if p.as_char() == '!');
+                let group = i + 1 + bang as usize;
+                if let Some(TokenTree::Group(bracket)) = tokens.get(group) {
+                    if bracket.delimiter() == Delimiter::Bracket
+                        && parse2::<Meta>(bracket.stream())
+                            .is_ok_and(|meta| is_control_meta(&meta, marker))
+                    {
+                        let next = tokens
+                            .get(group + 1)
+                            .map_or(end, |tt| tt.span().start().into());
+                        attrs.push(pound.span().start().into()..next);
+                        i = group + 1;
+                        continue;
+                    }
+                }
+            }
+            TokenTree::Group(group) => control_attrs(
+                group.stream(),
+                group.span_close().start().into(),
+                marker,
+                attrs,
+            ),
+            _ => {}
+        }
+        i += 1;
+    }
+}
 impl<'ast> Visit<'ast> for SpanCollector {
     fn visit_bin_op(&mut self, node: &'ast BinOp) {
         if let BinOp::BitAnd(and) = node {
@@ -115,11 +490,15 @@ impl<'ast> Visit<'ast> for SpanCollector {
         if let Item::Verbatim(tokens) = node {
             self.visit_token_stream(tokens);
         }
+        self.item_starts.insert(node.span().start().into());
         visit::visit_item(self, node);
     }
     fn visit_macro(&mut self, node: &'ast Macro) {
         visit::visit_macro(self, node);
-        self.visit_token_stream(&node.tokens);
+        match MacroArgs::parse(node) {
+            Some(args) => args.visit(self),
+            None => self.visit_token_stream(&node.tokens),
+        }
     }
     fn visit_meta_list(&mut self, node: &'ast syn::MetaList) {
         visit::visit_meta_list(self, node);
@@ -155,13 +534,14 @@ mod tests {
     #[test_case("fn main(){let x = true;println!(\"{}\",x);}"; "single line")]
     #[test_case("fn main(){\n\tlet x = true;\n\tprintln!(\"{}\",x);\n}"; "multiple line")]
     #[test_case("fn main(){\r\n\tlet x = true;\r\n\tprintln!(\"{}\",x);\r\n}"; "crlf")]
+    #[test_case("// 日本語のコメント\nconst C: char = 'é'; fn main() { let s = \"ü\"; }"; "multibyte")]
     fn test_lined_source(content: &str) -> Result<(), syn::Error> {
         let source = LinedSource::new(content);
         let file = parse_file(content)?;
         for item in file.items {
             let span = item.span();
             let item_str = source.get(&(span.start().into()..span.end().into()));
-            assert!(item_str.is_some());
+            assert!(item_str.is_ok());
             let item_str = item_str.unwrap();
             assert!(!item_str.starts_with(' '));
             assert!(!item_str.ends_with(' '));
@@ -170,6 +550,114 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_lined_source_pos() {
+        let source = LinedSource::new("é = 1;\n日本\nx");
+        assert_eq!(source.pos(&LineColumn::new(1, 1)), Ok(2));
+        assert_eq!(source.pos(&LineColumn::new(2, 1)), Ok(11));
+        assert_eq!(source.pos(&LineColumn::new(2, 2)), Ok(14));
+        assert_eq!(source.pos(&LineColumn::new(3, 1)), Ok(16));
+        assert_eq!(
+            source.pos(&LineColumn::new(0, 0)),
+            Err(PositionError::LineZero)
+        );
+        assert_eq!(
+            source.pos(&LineColumn::new(3, 2)),
+            Err(PositionError::OutOfRange(LineColumn::new(3, 2)))
+        );
+        assert_eq!(
+            source.pos(&LineColumn::new(4, 0)),
+            Err(PositionError::OutOfRange(LineColumn::new(4, 0)))
+        );
+    }
+
+    #[test]
+    fn test_skip_regions() {
+        let content = "a\n// rust_minify:off\nb\n  //rust_minify:on\n// rust_minify:off\n// rust_minify:on\n  // rust_minify:off\nc\r\nd\n";
+        let source = LinedSource::new(content);
+        assert_eq!(
+            source.skip_regions(),
+            vec![
+                SkipRegion {
+                    range: LineColumn::new(3, 0)..LineColumn::new(4, 0),
+                    source: "b",
+                },
+                SkipRegion {
+                    range: LineColumn::new(8, 0)..LineColumn::new(11, 0),
+                    source: "c\r\nd",
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_skip_regions_ignored_markers() {
+        let content = indoc!(
+            r#"
+            let s = "
+            // rust_minify:off
+            ";
+            /*
+            // rust_minify:off
+            */
+            f(); // rust_minify:off
+            /* rust_minify:off */
+            "#
+        );
+        assert_eq!(LinedSource::new(content).skip_regions(), vec![]);
+    }
+
+    #[test]
+    fn test_comments() {
+        let content = indoc!(
+            r##"
+            //! inner
+            /* a /* nested */ b */ fn f<'a>(x: &'a str) -> char {
+                let s = "// not a comment"; let r = r#"/* "# nor this */"#;
+                let c = '\''; let d = '"'; // 日本 @license
+                br"//" ; 'x' /** doc */
+            }
+        "##
+        );
+        let source = LinedSource::new(content);
+        let comments = source.comments();
+        assert_eq!(
+            comments
+                .iter()
+                .map(|c| (c.range.clone(), c.source, c.is_doc(), c.is_preserved()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    LineColumn::new(1, 0)..LineColumn::new(1, 9),
+                    "//! inner",
+                    true,
+                    false
+                ),
+                (
+                    LineColumn::new(2, 0)..LineColumn::new(2, 22),
+                    "/* a /* nested */ b */",
+                    false,
+                    false
+                ),
+                (
+                    LineColumn::new(4, 31)..LineColumn::new(4, 45),
+                    "// 日本 @license",
+                    false,
+                    true
+                ),
+                (
+                    LineColumn::new(5, 17)..LineColumn::new(5, 27),
+                    "/** doc */",
+                    true,
+                    false
+                ),
+            ]
+        );
+        for comment in comments {
+            assert_eq!(source.get(&comment.range), Ok(comment.source));
+        }
+    }
+
     #[test]
     fn test_span_collect() -> Result<(), syn::Error> {
         let content = indoc!(
@@ -180,7 +668,8 @@ mod tests {
                 let x = true& &true;
             //234567890123456789012345
                 if x && true {
-                    println!("{}", x);
+                    println!("{}", x& &x);
+                    m!(x);
             //234567890123456789012345
                 }
             }
@@ -189,15 +678,18 @@ mod tests {
         let file = parse_file(content)?;
         let mut sc = SpanCollector::new();
         sc.collect(&file);
+        let mut bitwise_and: Vec<_> = sc.bitwise_and.iter().cloned().collect();
+        bitwise_and.sort();
         assert_eq!(
-            sc.bitwise_and.iter().cloned().collect::<Vec<_>>(),
-            vec![LineColumn::new(4, 16)]
+            bitwise_and,
+            vec![LineColumn::new(4, 16), LineColumn::new(7, 24)]
         );
+        // the arguments of `println!` are parsed
         assert_eq!(
             sc.tokens,
             vec![
                 LineColumn::new(1, 11)..LineColumn::new(1, 21),
-                LineColumn::new(7, 17)..LineColumn::new(7, 24)
+                LineColumn::new(8, 11)..LineColumn::new(8, 12)
             ]
         );
         Ok(())
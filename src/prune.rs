@@ -0,0 +1,367 @@
+use crate::attr::{is_minify_skip, ItemExt};
+use fxhash::FxHashSet;
+use proc_macro2::{TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::{parse2, File, ForeignItem, Item, ItemImpl, TraitItem, Type, UseTree};
+
+/// Removes items which are not reachable from `roots`, or from `main` if `roots` is empty.
+///
+/// Reachability is name based: an item is reachable if its name appears in a reachable item,
+/// including inside macro invocations and format strings.
+/// A trait is also reachable if one of its methods is named, and an `impl` is reachable
+/// if the local types and traits it refers to in its header are.
+/// Macro invocations, `extern crate`, imports of external or glob paths, skipped items and
+/// items exported by `#[no_mangle]`/`#[export_name]` are always kept.
+/// If no root is defined in the file, nothing is removed.
+pub fn remove_unreachable(file: &mut File, roots: &[String]) {
+    let roots: Vec<String> = if roots.is_empty() {
+        vec!["main".to_string()]
+    } else {
+        roots.to_vec()
+    };
+    let mut defined = FxHashSet::default();
+    collect_defined(&file.items, &mut defined);
+    if !roots.iter().any(|root| defined.contains(root)) {
+        return;
+    }
+
+    let mut reachability = Reachability {
+        defined,
+        reachable: roots.into_iter().collect(),
+        visited: FxHashSet::default(),
+        changed: true,
+    };
+    for attr in &file.attrs {
+        reachability.reach_tokens(attr.to_token_stream());
+    }
+    while reachability.changed {
+        reachability.changed = false;
+        reachability.visit_items(&file.items, &mut vec![]);
+    }
+    reachability.retain_items(&mut file.items, &mut vec![]);
+}
+
+fn collect_defined(items: &[Item], defined: &mut FxHashSet<String>) {
+    for item in items {
+        if !matches!(item, Item::Use(_)) {
+            defined.extend(defined_names(item));
+        }
+        match item {
+            Item::Mod(item) => {
+                if let Some((_, items)) = &item.content {
+                    collect_defined(items, defined);
+                }
+            }
+            // such as unmarked items in the opt-in mode, which are always kept
+            Item::Verbatim(tokens) => match parse2::<Item>(tokens.clone()) {
+                Ok(Item::Verbatim(_)) | Err(_) => {}
+                Ok(item) => collect_defined(&[item], defined),
+            },
+            _ => {}
+        }
+    }
+}
+
+fn defined_names(item: &Item) -> Vec<String> {
+    let ident = match item {
+        Item::Const(item) => &item.ident,
+        Item::Enum(item) => &item.ident,
+        Item::ExternCrate(item) => item.rename.as_ref().map_or(&item.ident, |(_, r)| r),
+        Item::Fn(item) => &item.sig.ident,
+        Item::Macro(item) => match &item.ident {
+            Some(ident) => ident,
+            None => return vec![],
+        },
+        Item::Mod(item) => &item.ident,
+        Item::Static(item) => &item.ident,
+        Item::Struct(item) => &item.ident,
+        Item::Trait(item) => &item.ident,
+        Item::TraitAlias(item) => &item.ident,
+        Item::Type(item) => &item.ident,
+        Item::Union(item) => &item.ident,
+        Item::ForeignMod(item) => {
+            return item
+                .items
+                .iter()
+                .filter_map(|item| match item {
+                    ForeignItem::Fn(item) => Some(item.sig.ident.to_string()),
+                    ForeignItem::Static(item) => Some(item.ident.to_string()),
+                    ForeignItem::Type(item) => Some(item.ident.to_string()),
+                    _ => None,
+                })
+                .collect();
+        }
+        Item::Use(item) => {
+            let mut names = vec![];
+            use_tree_names(&item.tree, &mut names);
+            return names;
+        }
+        _ => return vec![],
+    };
+    vec![ident.to_string()]
+}
+
+fn use_tree_names(tree: &UseTree, names: &mut Vec<String>) {
+    match tree {
+        UseTree::Path(path) => use_tree_names(&path.tree, names),
+        UseTree::Name(name) => names.push(name.ident.to_string()),
+        UseTree::Rename(rename) => names.push(rename.rename.to_string()),
+        UseTree::Glob(_) => {}
+        UseTree::Group(group) => {
+            for tree in &group.items {
+                use_tree_names(tree, names);
+            }
+        }
+    }
+}
+
+fn has_glob(tree: &UseTree) -> bool {
+    match tree {
+        UseTree::Path(path) => has_glob(&path.tree),
+        UseTree::Glob(_) => true,
+        UseTree::Group(group) => group.items.iter().any(has_glob),
+        UseTree::Name(_) | UseTree::Rename(_) => false,
+    }
+}
+
+/// Returns the last segments of the paths in `ty`, which name the implemented types.
+fn type_names(ty: &Type, names: &mut Vec<String>) {
+    match ty {
+        Type::Path(ty) => {
+            if let Some(segment) = ty.path.segments.last() {
+                names.push(segment.ident.to_string());
+            }
+        }
+        Type::Reference(ty) => type_names(&ty.elem, names),
+        Type::Array(ty) => type_names(&ty.elem, names),
+        Type::Slice(ty) => type_names(&ty.elem, names),
+        Type::Ptr(ty) => type_names(&ty.elem, names),
+        Type::Paren(ty) => type_names(&ty.elem, names),
+        Type::Group(ty) => type_names(&ty.elem, names),
+        Type::Tuple(ty) => {
+            for elem in &ty.elems {
+                type_names(elem, names);
+            }
+        }
+        _ => {}
+    }
+}
+
+struct Reachability {
+    defined: FxHashSet<String>,
+    reachable: FxHashSet<String>,
+    /// The positions of the reachable items, as indices from the file root.
+    visited: FxHashSet<Vec<usize>>,
+    changed: bool,
+}
+
+impl Reachability {
+    fn is_always_reachable(item: &Item) -> bool {
+        let exported = item.get_attributes().is_some_and(|attrs| {
+            is_minify_skip(attrs)
+                || attrs.iter().any(|attr| {
+                    ["no_mangle", "export_name", "used"]
+                        .iter()
+                        .any(|name| attr.path().is_ident(name))
+                })
+        });
+        exported
+            || match item {
+                Item::Macro(item) => item.ident.is_none(),
+                Item::Use(item) => has_glob(&item.tree),
+                Item::ExternCrate(_) | Item::Verbatim(_) => true,
+                _ => false,
+            }
+    }
+
+    fn is_reachable(&self, item: &Item) -> bool {
+        if Self::is_always_reachable(item) {
+            return true;
+        }
+        match item {
+            Item::Impl(item) => self.is_impl_reachable(item),
+            // methods may be called without naming the trait
+            Item::Trait(item) => {
+                self.reachable.contains(&item.ident.to_string())
+                    || item.items.iter().any(|item| match item {
+                        TraitItem::Fn(item) => self.reachable.contains(&item.sig.ident.to_string()),
+                        TraitItem::Const(item) => self.reachable.contains(&item.ident.to_string()),
+                        _ => false,
+                    })
+            }
+            // external traits may be imported for their methods
+            Item::Use(_) => defined_names(item)
+                .iter()
+                .any(|name| self.reachable.contains(name) || !self.defined.contains(name)),
+            _ => defined_names(item)
+                .iter()
+                .any(|name| self.reachable.contains(name)),
+        }
+    }
+
+    fn is_impl_reachable(&self, item: &ItemImpl) -> bool {
+        let mut names = vec![];
+        type_names(&item.self_ty, &mut names);
+        if let Some((_, path, _)) = &item.trait_ {
+            if let Some(segment) = path.segments.last() {
+                names.push(segment.ident.to_string());
+            }
+        }
+        names
+            .iter()
+            .all(|name| !self.defined.contains(name) || self.reachable.contains(name))
+    }
+
+    fn visit_items(&mut self, items: &[Item], position: &mut Vec<usize>) {
+        for (i, item) in items.iter().enumerate() {
+            position.push(i);
+            if let Item::Mod(module) = item {
+                if let Some((_, items)) = &module.content {
+                    self.visit_items(items, position);
+                }
+            }
+            if !self.visited.contains(position) && self.is_reachable(item) {
+                self.visited.insert(position.clone());
+                self.changed = true;
+                match item {
+                    Item::Mod(module) => {
+                        for attr in &module.attrs {
+                            self.reach_tokens(attr.to_token_stream());
+                        }
+                    }
+                    _ => self.reach_tokens(item.to_token_stream()),
+                }
+                self.reachable.extend(defined_names(item));
+            }
+            position.pop();
+        }
+    }
+
+    fn reach_tokens(&mut self, tokens: TokenStream) {
+        for tt in tokens {
+            match tt {
+                TokenTree::Group(group) => self.reach_tokens(group.stream()),
+                TokenTree::Ident(ident) => {
+                    self.reachable.insert(ident.to_string());
+                }
+                TokenTree::Literal(literal) => {
+                    // may capture identifiers in format strings: `"{x}"`
+                    let literal = literal.to_string();
+                    if literal.contains('{') {
+                        for word in literal.split(|c: char| !c.is_alphanumeric() && c != '_') {
+                            if !word.is_empty() {
+                                self.reachable.insert(word.to_string());
+                            }
+                        }
+                    }
+                }
+                TokenTree::Punct(_) => {}
+            }
+        }
+    }
+
+    fn retain_items(&self, items: &mut Vec<Item>, position: &mut Vec<usize>) {
+        let mut i = 0;
+        items.retain_mut(|item| {
+            position.push(i);
+            i += 1;
+            let mut keep = self.visited.contains(position);
+            if let Item::Mod(module) = item {
+                if let Some((_, items)) = &mut module.content {
+                    self.retain_items(items, position);
+                    keep |= !items.is_empty();
+                }
+            }
+            position.pop();
+            keep
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use syn::parse_file;
+    use test_case::test_case;
+
+    #[test_case(
+        indoc!(r#"
+            fn main() { let _ = a(); }
+            fn a() -> S { S }
+            fn b() {}
+            struct S;
+            struct T;
+        "#),
+        "fn main () { let _ = a () ; } fn a () -> S { S } struct S ;",
+        &[];
+        "functions and structs"
+    )]
+    #[test_case(
+        indoc!(r#"
+            fn main() { S.f(); }
+            struct S;
+            trait Tr { fn f(&self); }
+            impl Tr for S { fn f(&self) {} }
+            impl Tr for u8 { fn f(&self) {} }
+            impl Clone for T { fn clone(&self) -> T { T } }
+            struct T;
+        "#),
+        "fn main () { S . f () ; } struct S ; trait Tr { fn f (& self) ; } impl Tr for S { fn f (& self) { } } impl Tr for u8 { fn f (& self) { } }",
+        &[];
+        "impls"
+    )]
+    #[test_case(
+        indoc!(r#"
+            fn main() { m!(); println!("{X}"); }
+            macro_rules! m { () => { f() } }
+            macro_rules! n { () => {} }
+            fn f() {}
+            const X: u8 = 0;
+            mod a { pub fn g() {} pub fn h() {} }
+            mod b { use super::*; }
+        "#),
+        "fn main () { m ! () ; println ! (\"{X}\") ; } macro_rules ! m { () => { f () } } fn f () { } const X : u8 = 0 ; mod b { use super :: * ; }",
+        &[];
+        "macros and modules"
+    )]
+    #[test_case(
+        indoc!(r#"
+            mod a { pub fn g() { super::h() } pub fn i() {} }
+            fn h() {}
+            fn main() {}
+        "#),
+        "mod a { pub fn g () { super :: h () } } fn h () { }",
+        &["g"];
+        "user roots"
+    )]
+    #[test_case(
+        indoc!(r#"
+            fn main() { 0u8.f(); }
+            mod a {
+                pub trait Tr { fn f(&self) {} }
+                impl Tr for u8 {}
+                pub trait Unused { fn g(&self) {} }
+                impl Unused for u8 {}
+            }
+            use a::Tr;
+            use a::Unused;
+            use std::io::Write;
+        "#),
+        "fn main () { 0u8 . f () ; } mod a { pub trait Tr { fn f (& self) { } } impl Tr for u8 { } } use a :: Tr ; use std :: io :: Write ;",
+        &[];
+        "trait method dispatch"
+    )]
+    #[test_case(
+        "pub fn f() {} fn g() {}",
+        "pub fn f () { } fn g () { }",
+        &[];
+        "no roots"
+    )]
+    fn test_remove_unreachable(content: &str, expected: &str, roots: &[&str]) {
+        let mut file = parse_file(content).unwrap();
+        let roots: Vec<String> = roots.iter().map(|s| s.to_string()).collect();
+        remove_unreachable(&mut file, &roots);
+        assert_eq!(file.to_token_stream().to_string(), expected);
+    }
+}
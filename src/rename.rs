@@ -0,0 +1,1094 @@
+use crate::attr::{is_minify_skip, scoped_option, ItemExt, SkippedNodes};
+use fxhash::{FxHashMap, FxHashSet};
+use proc_macro2::{Ident, TokenStream, TokenTree};
+use quote::ToTokens;
+use std::mem::{replace, take};
+use syn::{
+    token,
+    visit::{self, Visit},
+    visit_mut::{self, VisitMut},
+    Attribute, Block, Expr, ExprField, ExprMethodCall, ExprPath, ExprStruct, ExprUnary, FieldPat,
+    FieldValue, Fields, File, ForeignItem, ImplItem, ImplItemFn, Item, ItemFn, Lifetime, Local,
+    Macro, Member, Meta, Pat, PatIdent, PatStruct, PatType, Path, Signature, TraitItem,
+    TraitItemFn, Type, TypePath, UnOp, UseTree, Variant, Visibility,
+};
+
+/// Strict and reserved keywords of all editions, which can not be used as identifiers.
+const KEYWORDS: [&str; 52] = [
+    "Self", "abstract", "as", "async", "await", "become", "box", "break", "const", "continue",
+    "crate", "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if",
+    "impl", "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub",
+    "ref", "return", "self", "static", "struct", "super", "trait", "true", "try", "type", "typeof",
+    "union", "unsafe", "unsized", "use", "virtual", "where", "while",
+];
+
+pub fn is_keyword(s: &str) -> bool {
+    KEYWORDS.contains(&s) || s == "yield" || s == "_"
+}
+
+/// Generates identifiers in order of length, skipping keywords.
+#[derive(Debug, Clone)]
+pub struct ShortNames {
+    first: &'static [u8],
+    rest: &'static [u8],
+    /// Indices into `first` and `rest` of the next name.
+    indices: Vec<usize>,
+}
+
+impl ShortNames {
+    pub const LOWER: &'static [u8] = b"abcdefghijklmnopqrstuvwxyz";
+    pub const UPPER: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    pub const ALNUM: &'static [u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+
+    /// Creates a generator of names starting with one of `first` followed by `rest`.
+    pub fn new(first: &'static [u8], rest: &'static [u8]) -> Self {
+        Self {
+            first,
+            rest,
+            indices: vec![0],
+        }
+    }
+
+    fn advance(&mut self) {
+        for (i, index) in self.indices.iter_mut().enumerate().rev() {
+            *index += 1;
+            let len = if i == 0 {
+                self.first.len()
+            } else {
+                self.rest.len()
+            };
+            if *index < len {
+                return;
+            }
+            *index = 0;
+        }
+        self.indices.push(0);
+    }
+}
+
+impl Iterator for ShortNames {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let name: String = self
+                .indices
+                .iter()
+                .enumerate()
+                .map(|(i, &index)| {
+                    if i == 0 {
+                        self.first[index] as char
+                    } else {
+                        self.rest[index] as char
+                    }
+                })
+                .collect();
+            self.advance();
+            if !is_keyword(&name) {
+                return Some(name);
+            }
+        }
+    }
+}
+
+/// Collects identifiers in `tokens` and words of literals, which may be captured
+/// identifiers of format strings like `"{x}"`.
+pub fn collect_token_idents(tokens: TokenStream, idents: &mut FxHashSet<String>) {
+    for tt in tokens {
+        match tt {
+            TokenTree::Group(group) => collect_token_idents(group.stream(), idents),
+            TokenTree::Ident(ident) => {
+                idents.insert(ident.to_string());
+            }
+            TokenTree::Literal(literal) => {
+                let literal = literal.to_string();
+                if literal.contains('{') {
+                    let words = literal.split(|c: char| !c.is_alphanumeric() && c != '_');
+                    idents.extend(words.filter(|w| !w.is_empty()).map(str::to_string));
+                }
+            }
+            TokenTree::Punct(_) => {}
+        }
+    }
+}
+
+pub fn count_token_idents(tokens: TokenStream, counts: &mut FxHashMap<String, usize>) {
+    for tt in tokens {
+        match tt {
+            TokenTree::Group(group) => count_token_idents(group.stream(), counts),
+            TokenTree::Ident(ident) => *counts.entry(ident.to_string()).or_default() += 1,
+            TokenTree::Literal(_) | TokenTree::Punct(_) => {}
+        }
+    }
+}
+
+/// Collects the identifiers of the nodes with the skip attribute found by `visit`, which keep
+/// their names since the nodes are emitted as they are.
+pub fn collect_skipped_idents<'ast>(
+    visit: impl FnOnce(&mut dyn Visit<'ast>),
+    idents: &mut FxHashSet<String>,
+) {
+    visit(&mut SkippedNodes(|tokens, _: &Attribute| {
+        collect_token_idents(tokens, idents)
+    }));
+}
+
+/// Assigns the shortest names not in `taken` to `names`, ordered by descending count.
+pub fn assign_names(
+    mut names: Vec<(String, usize)>,
+    taken: &FxHashSet<String>,
+    generator: ShortNames,
+) -> FxHashMap<String, String> {
+    names.sort_by(|(n0, c0), (n1, c1)| c1.cmp(c0).then_with(|| n0.cmp(n1)));
+    let mut generator = generator.filter(|name| !taken.contains(name));
+    names
+        .into_iter()
+        .filter_map(|(name, _)| Some((name, generator.next()?)))
+        .collect()
+}
+
+/// Renames local bindings in function bodies to the shortest free names.
+///
+/// `let` bindings, closure, function and pattern bindings are renamed consistently in each
+/// function, so shadowing is preserved. A name is left as it is if it starts with an uppercase
+/// letter or `_`, names an item of the file, is in `kept`, or appears in a macro invocation or
+/// a skipped node of the function.
+///
+/// Functions are renamed if `enabled`, unless overridden by
+/// `#[rust_minify::options(rename_locals = ..)]` on an enclosing item.
+pub fn rename_locals(file: &mut File, enabled: bool, kept: &FxHashSet<String>) {
+    let mut items = ItemNames::default();
+    items.visit_file(file);
+    items.0.extend(kept.iter().cloned());
+    LocalRenamer {
+        items: items.0,
+        enabled: scoped_option(&file.attrs, "rename_locals").unwrap_or(enabled),
+    }
+    .visit_file_mut(file);
+}
+
+/// Returns the name defined by `item`, if any.
+fn item_ident(item: &Item) -> Option<&Ident> {
+    Some(match item {
+        Item::Const(item) => &item.ident,
+        Item::Enum(item) => &item.ident,
+        Item::ExternCrate(item) => &item.ident,
+        Item::Fn(item) => &item.sig.ident,
+        Item::Macro(item) => return item.ident.as_ref(),
+        Item::Mod(item) => &item.ident,
+        Item::Static(item) => &item.ident,
+        Item::Struct(item) => &item.ident,
+        Item::Trait(item) => &item.ident,
+        Item::TraitAlias(item) => &item.ident,
+        Item::Type(item) => &item.ident,
+        Item::Union(item) => &item.ident,
+        _ => return None,
+    })
+}
+
+/// Collects names of items, including nested ones and enum variants.
+#[derive(Default)]
+struct ItemNames(FxHashSet<String>);
+
+impl<'ast> Visit<'ast> for ItemNames {
+    fn visit_item(&mut self, node: &'ast Item) {
+        match node {
+            Item::Use(item) => collect_token_idents(item.tree.to_token_stream(), &mut self.0),
+            // such as unmarked items in the opt-in mode
+            Item::Verbatim(tokens) => collect_token_idents(tokens.clone(), &mut self.0),
+            _ => self.0.extend(item_ident(node).map(Ident::to_string)),
+        }
+        visit::visit_item(self, node);
+    }
+
+    fn visit_variant(&mut self, node: &'ast Variant) {
+        self.0.insert(node.ident.to_string());
+        visit::visit_variant(self, node);
+    }
+}
+
+struct LocalRenamer {
+    items: FxHashSet<String>,
+    enabled: bool,
+}
+
+impl LocalRenamer {
+    fn rename_fn(&self, sig: &mut Signature, block: &mut Block) {
+        let mut bindings = Bindings::default();
+        bindings.visit_signature(sig);
+        bindings.visit_block(block);
+        let mut skipped = FxHashSet::default();
+        collect_skipped_idents(
+            |v| {
+                v.visit_signature(sig);
+                v.visit_block(block);
+            },
+            &mut skipped,
+        );
+
+        let candidates: Vec<(String, usize)> = bindings
+            .names
+            .into_iter()
+            .filter(|(name, _)| {
+                !name.starts_with(|c: char| c.is_uppercase() || c == '_')
+                    && !is_keyword(name)
+                    && !self.items.contains(name)
+                    && !bindings.in_macro.contains(name)
+                    && !skipped.contains(name)
+            })
+            .map(|(name, count)| (name.clone(), count + bindings.uses.get(&name).unwrap_or(&0)))
+            .collect();
+        if candidates.is_empty() {
+            return;
+        }
+
+        // names only appearing as candidates can be reused
+        let mut counts = FxHashMap::default();
+        count_token_idents(sig.to_token_stream(), &mut counts);
+        count_token_idents(block.to_token_stream(), &mut counts);
+        let mut taken = self.items.clone();
+        taken.extend(counts.into_iter().filter_map(|(name, count)| {
+            let renamed = candidates.iter().find(|(n, _)| *n == name);
+            match renamed {
+                Some((_, c)) if *c == count => None,
+                _ => Some(name),
+            }
+        }));
+        let renames = assign_names(
+            candidates,
+            &taken,
+            ShortNames::new(ShortNames::LOWER, ShortNames::ALNUM),
+        );
+        let mut renamer = Renamer { renames: &renames };
+        renamer.visit_signature_mut(sig);
+        renamer.visit_block_mut(block);
+    }
+}
+
+impl VisitMut for LocalRenamer {
+    fn visit_item_mut(&mut self, node: &mut Item) {
+        let enabled = self.enabled;
+        let attrs = node.get_attributes().unwrap_or_default();
+        self.enabled = scoped_option(attrs, "rename_locals").unwrap_or(enabled);
+        visit_mut::visit_item_mut(self, node);
+        self.enabled = enabled;
+    }
+
+    fn visit_item_fn_mut(&mut self, node: &mut ItemFn) {
+        visit_mut::visit_item_fn_mut(self, node);
+        if self.enabled {
+            self.rename_fn(&mut node.sig, &mut node.block);
+        }
+    }
+
+    fn visit_impl_item_fn_mut(&mut self, node: &mut ImplItemFn) {
+        visit_mut::visit_impl_item_fn_mut(self, node);
+        if self.enabled {
+            self.rename_fn(&mut node.sig, &mut node.block);
+        }
+    }
+
+    fn visit_trait_item_fn_mut(&mut self, node: &mut TraitItemFn) {
+        visit_mut::visit_trait_item_fn_mut(self, node);
+        if let (true, Some(block)) = (self.enabled, &mut node.default) {
+            self.rename_fn(&mut node.sig, block);
+        }
+    }
+}
+
+/// Collects bindings of a function, without descending into nested items.
+#[derive(Default)]
+struct Bindings {
+    /// Bound names and the number of bindings.
+    names: FxHashMap<String, usize>,
+    /// Single identifier paths and the number of occurrences.
+    uses: FxHashMap<String, usize>,
+    in_macro: FxHashSet<String>,
+}
+
+impl<'ast> Visit<'ast> for Bindings {
+    fn visit_item(&mut self, _: &'ast Item) {}
+
+    fn visit_pat_ident(&mut self, node: &'ast PatIdent) {
+        *self.names.entry(node.ident.to_string()).or_default() += 1;
+        visit::visit_pat_ident(self, node);
+    }
+
+    fn visit_expr_path(&mut self, node: &'ast ExprPath) {
+        if let Some(ident) = local_path(node) {
+            *self.uses.entry(ident.to_string()).or_default() += 1;
+        }
+        visit::visit_expr_path(self, node);
+    }
+
+    fn visit_macro(&mut self, node: &'ast Macro) {
+        collect_token_idents(node.tokens.clone(), &mut self.in_macro);
+        visit::visit_macro(self, node);
+    }
+}
+
+/// Returns the identifier if `path` is a single identifier, which may be a local variable.
+fn local_path(path: &ExprPath) -> Option<&Ident> {
+    if path.qself.is_none() && path.path.leading_colon.is_none() && path.path.segments.len() == 1 {
+        let segment = &path.path.segments[0];
+        if segment.arguments.is_none() {
+            return Some(&segment.ident);
+        }
+    }
+    None
+}
+
+struct Renamer<'a> {
+    renames: &'a FxHashMap<String, String>,
+}
+
+impl Renamer<'_> {
+    fn rename(&self, ident: &mut Ident) -> bool {
+        match self.renames.get(&ident.to_string()) {
+            Some(name) => {
+                *ident = Ident::new(name, ident.span());
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl VisitMut for Renamer<'_> {
+    fn visit_item_mut(&mut self, _: &mut Item) {}
+
+    fn visit_macro_mut(&mut self, _: &mut Macro) {}
+
+    fn visit_pat_ident_mut(&mut self, node: &mut PatIdent) {
+        self.rename(&mut node.ident);
+        visit_mut::visit_pat_ident_mut(self, node);
+    }
+
+    fn visit_expr_path_mut(&mut self, node: &mut ExprPath) {
+        if local_path(node).is_some() {
+            self.rename(&mut node.path.segments[0].ident);
+        }
+        visit_mut::visit_expr_path_mut(self, node);
+    }
+
+    fn visit_field_value_mut(&mut self, node: &mut FieldValue) {
+        // expand the shorthand `S { x }` to `S { x: y }`
+        if node.colon_token.is_none() {
+            if let (Member::Named(member), Expr::Path(path)) = (&node.member, &mut node.expr) {
+                if local_path(path).is_some() && self.rename(&mut path.path.segments[0].ident) {
+                    node.colon_token = Some(token::Colon(member.span()));
+                }
+                return;
+            }
+        }
+        visit_mut::visit_field_value_mut(self, node);
+    }
+
+    fn visit_field_pat_mut(&mut self, node: &mut FieldPat) {
+        // expand the shorthand `S { x }` to `S { x: y }`
+        if node.colon_token.is_none() {
+            if let (Member::Named(member), Pat::Ident(pat)) = (&node.member, &mut *node.pat) {
+                if self.rename(&mut pat.ident) {
+                    node.colon_token = Some(token::Colon(member.span()));
+                }
+                if let Some((_, subpat)) = &mut pat.subpat {
+                    self.visit_pat_mut(subpat);
+                }
+                return;
+            }
+        }
+        visit_mut::visit_field_pat_mut(self, node);
+    }
+}
+
+/// Renames private items, their fields and methods consistently across the file.
+///
+/// Each renamed name is replaced at all of its occurrences, so the pass has no type information
+/// and relies on the exclusion of names which may refer to something outside of the file:
+/// names of `pub` items, fields and methods, items of traits which are not defined in the file,
+/// `#[no_mangle]`/`#[export_name]` and `extern` symbols, `main`, segments of external paths,
+/// identifiers in macro invocations, attributes and skipped nodes, and the names in `kept`.
+/// A method or field name is also kept if it is called or accessed on a receiver which is not
+/// known to have a type of the file, like `v.iter().find(..)`, since it may belong to an external
+/// type, and a field name is kept if it is used in a struct expression or pattern of a type which
+/// is not defined in the file, like `Range { start, end }`. Receivers are known from `self` in
+/// inherent and trait implementations for the types and from bindings annotated with the types
+/// or initialized by their struct expressions.
+///
+/// Names are renamed if `enabled`, unless overridden by
+/// `#[rust_minify::options(rename_items = ..)]` on an enclosing item; no identifier in an item
+/// where the pass is disabled is renamed.
+pub fn rename_items(file: &mut File, enabled: bool, kept: &FxHashSet<String>) {
+    let mut items = PrivateItems {
+        enabled: scoped_option(&file.attrs, "rename_items").unwrap_or(enabled),
+        ..Default::default()
+    };
+    items.visit_file(file);
+    let PrivateItems {
+        defined,
+        candidates,
+        mut excluded,
+        traits,
+        trait_impls,
+        paths,
+        types,
+        members,
+        ..
+    } = items;
+    excluded.insert("main".to_string());
+    excluded.extend(kept.iter().cloned());
+    collect_skipped_idents(|v| v.visit_file(file), &mut excluded);
+    for (member, ty) in members {
+        if !ty.is_some_and(|ty| types.contains(&ty)) {
+            excluded.insert(member);
+        }
+    }
+    for (trait_name, names) in trait_impls {
+        if !traits.contains(&trait_name) {
+            excluded.extend(names);
+        }
+    }
+    for path in paths {
+        let local = path.first().is_some_and(|first| {
+            ["crate", "self", "super", "Self"].contains(&first.as_str()) || defined.contains(first)
+        });
+        if !local {
+            excluded.extend(path);
+        }
+    }
+
+    let mut counts = FxHashMap::default();
+    count_token_idents(file.to_token_stream(), &mut counts);
+    let (upper, lower): (Vec<_>, Vec<_>) = candidates
+        .into_iter()
+        .filter(|name| !excluded.contains(name))
+        .map(|name| {
+            let count = counts.get(&name).copied().unwrap_or_default();
+            (name, count)
+        })
+        .partition(|(name, _)| name.starts_with(char::is_uppercase));
+    if upper.is_empty() && lower.is_empty() {
+        return;
+    }
+    let mut taken = FxHashSet::default();
+    collect_token_idents(file.to_token_stream(), &mut taken);
+    let mut renames = assign_names(
+        upper,
+        &taken,
+        ShortNames::new(ShortNames::UPPER, ShortNames::ALNUM),
+    );
+    renames.extend(assign_names(
+        lower,
+        &taken,
+        ShortNames::new(ShortNames::LOWER, ShortNames::ALNUM),
+    ));
+    ItemRenamer { renames: &renames }.visit_file_mut(file);
+}
+
+/// Derives whose behaviour does not depend on the names of the type and its fields, unlike
+/// `Debug` which prints them.
+const STD_DERIVES: [&str; 8] = [
+    "Clone",
+    "Copy",
+    "Default",
+    "Eq",
+    "Hash",
+    "Ord",
+    "PartialEq",
+    "PartialOrd",
+];
+
+fn is_private(vis: &Visibility) -> bool {
+    !matches!(vis, Visibility::Public(_))
+}
+
+/// Returns `true` if the symbol name of the item is exported, also with `#[unsafe(no_mangle)]`.
+fn is_exported(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        let path = match &attr.meta {
+            Meta::List(list) if list.path.is_ident("unsafe") => match list.parse_args::<Meta>() {
+                Ok(meta) => meta.path().clone(),
+                Err(_) => return false,
+            },
+            meta => meta.path().clone(),
+        };
+        path.is_ident("no_mangle") || path.is_ident("export_name")
+    })
+}
+
+/// Returns `true` if the fields of the type may be used by name by a derive macro.
+fn has_custom_derive(attrs: &[Attribute]) -> bool {
+    let mut custom = false;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("derive")) {
+        let _ = attr.parse_nested_meta(|meta| {
+            custom |= !STD_DERIVES.iter().any(|name| meta.path.is_ident(name));
+            Ok(())
+        });
+    }
+    custom
+}
+
+fn use_tree_paths(tree: &UseTree, prefix: &mut Vec<String>, paths: &mut Vec<Vec<String>>) {
+    match tree {
+        UseTree::Path(path) => {
+            prefix.push(path.ident.to_string());
+            use_tree_paths(&path.tree, prefix, paths);
+            prefix.pop();
+        }
+        UseTree::Name(name) => {
+            let mut path = prefix.clone();
+            path.push(name.ident.to_string());
+            paths.push(path);
+        }
+        UseTree::Rename(rename) => {
+            let mut path = prefix.clone();
+            path.push(rename.ident.to_string());
+            paths.push(path);
+        }
+        UseTree::Glob(_) => paths.push(prefix.clone()),
+        UseTree::Group(group) => {
+            for tree in &group.items {
+                use_tree_paths(tree, prefix, paths);
+            }
+        }
+    }
+}
+
+/// Collects private names and the names which must not be renamed.
+#[derive(Default)]
+struct PrivateItems {
+    /// Names of all items, which may start a local path.
+    defined: FxHashSet<String>,
+    candidates: FxHashSet<String>,
+    excluded: FxHashSet<String>,
+    /// Names of the traits defined in the file.
+    traits: FxHashSet<String>,
+    /// Names of implemented traits and of the items of the implementations.
+    trait_impls: Vec<(String, Vec<String>)>,
+    /// Segments of paths with more than one segment.
+    paths: Vec<Vec<String>>,
+    /// Names of the structs, enums and unions defined in the file.
+    types: FxHashSet<String>,
+    /// Names of called methods and accessed fields with the types of their receivers, if known.
+    members: Vec<(String, Option<String>)>,
+    /// The type of `self` in the current implementation.
+    self_ty: Option<String>,
+    /// The types of the bindings in the current function.
+    receivers: FxHashMap<String, Option<String>>,
+    /// Whether the names in the current item may be renamed.
+    enabled: bool,
+}
+
+impl PrivateItems {
+    fn add(&mut self, ident: &Ident, private: bool) {
+        if private && self.enabled {
+            self.candidates.insert(ident.to_string());
+        } else {
+            self.excluded.insert(ident.to_string());
+        }
+    }
+
+    /// Adds a type and its fields, which are kept if a derive macro may use their names.
+    fn add_type(&mut self, ident: &Ident, vis: &Visibility, attrs: &[Attribute], fields: &Fields) {
+        let custom_derive = has_custom_derive(attrs);
+        self.add(ident, is_private(vis) && !custom_derive);
+        self.add_fields(fields, !custom_derive);
+    }
+
+    fn add_fields(&mut self, fields: &Fields, private: bool) {
+        for field in fields {
+            if let Some(ident) = &field.ident {
+                self.add(ident, private && is_private(&field.vis));
+            }
+        }
+    }
+
+    /// Visits a function with the types of its bindings.
+    fn visit_fn<'ast>(&mut self, sig: &'ast Signature, block: Option<&'ast Block>) {
+        let mut receivers = ReceiverTypes {
+            self_ty: self.self_ty.clone(),
+            types: FxHashMap::default(),
+        };
+        receivers.visit_signature(sig);
+        if let Some(block) = block {
+            receivers.visit_block(block);
+        }
+        let outer = replace(&mut self.receivers, receivers.types);
+        self.visit_signature(sig);
+        if let Some(block) = block {
+            self.visit_block(block);
+        }
+        self.receivers = outer;
+    }
+
+    /// Returns the type of `expr` if it is `self` or a binding of a known type.
+    fn receiver_type(&self, expr: &Expr) -> Option<String> {
+        match expr {
+            Expr::Paren(expr) => self.receiver_type(&expr.expr),
+            Expr::Reference(expr) => self.receiver_type(&expr.expr),
+            Expr::Unary(ExprUnary {
+                op: UnOp::Deref(_),
+                expr,
+                ..
+            }) => self.receiver_type(expr),
+            Expr::Path(path) => match local_path(path) {
+                Some(ident) if ident == "self" => self.self_ty.clone(),
+                Some(ident) => self.receivers.get(&ident.to_string()).cloned().flatten(),
+                None => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Returns the name of `ty` if it is a single identifier, possibly behind references.
+fn type_name(ty: &Type, self_ty: Option<&String>) -> Option<String> {
+    match ty {
+        Type::Paren(ty) => type_name(&ty.elem, self_ty),
+        Type::Reference(ty) => type_name(&ty.elem, self_ty),
+        Type::Path(TypePath { qself: None, path }) => path_type_name(path, self_ty),
+        _ => None,
+    }
+}
+
+fn path_type_name(path: &Path, self_ty: Option<&String>) -> Option<String> {
+    match (&path.leading_colon, path.segments.first()) {
+        (None, Some(segment)) if path.segments.len() == 1 => {
+            if segment.ident == "Self" {
+                self_ty.cloned()
+            } else {
+                Some(segment.ident.to_string())
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Returns the type named by the path of a struct expression or pattern, which is the enum for
+/// a variant like `E::V { .. }`.
+fn struct_path_type(path: &Path, self_ty: Option<&String>) -> Option<String> {
+    match (&path.leading_colon, path.segments.first()) {
+        (None, Some(segment)) if path.segments.len() == 2 => {
+            path_type_name(&segment.ident.clone().into(), self_ty)
+        }
+        _ => path_type_name(path, self_ty),
+    }
+}
+
+/// Collects the types of the bindings of a function, without descending into nested items.
+///
+/// A name bound more than once with different or unknown types has no type.
+struct ReceiverTypes {
+    self_ty: Option<String>,
+    types: FxHashMap<String, Option<String>>,
+}
+
+impl ReceiverTypes {
+    fn bind(&mut self, ident: &Ident, ty: Option<String>) {
+        let name = ident.to_string();
+        match self.types.get_mut(&name) {
+            Some(bound) if *bound != ty => *bound = None,
+            Some(_) => {}
+            None => {
+                self.types.insert(name, ty);
+            }
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for ReceiverTypes {
+    fn visit_item(&mut self, _: &'ast Item) {}
+
+    fn visit_local(&mut self, node: &'ast Local) {
+        match (&node.pat, &node.init) {
+            (Pat::Ident(pat), Some(init)) if pat.subpat.is_none() => {
+                let ty = match &*init.expr {
+                    Expr::Struct(expr) if expr.qself.is_none() => {
+                        path_type_name(&expr.path, self.self_ty.as_ref())
+                    }
+                    _ => None,
+                };
+                self.bind(&pat.ident, ty);
+                visit::visit_local_init(self, init);
+            }
+            _ => visit::visit_local(self, node),
+        }
+    }
+
+    fn visit_pat_ident(&mut self, node: &'ast PatIdent) {
+        self.bind(&node.ident, None);
+        visit::visit_pat_ident(self, node);
+    }
+
+    fn visit_pat_type(&mut self, node: &'ast PatType) {
+        match &*node.pat {
+            Pat::Ident(pat) if pat.subpat.is_none() => {
+                let ty = type_name(&node.ty, self.self_ty.as_ref());
+                self.bind(&pat.ident, ty);
+            }
+            _ => visit::visit_pat_type(self, node),
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for PrivateItems {
+    fn visit_item(&mut self, node: &'ast Item) {
+        if matches!(node, Item::Verbatim(_)) || node.get_attributes().is_some_and(is_minify_skip) {
+            collect_token_idents(node.to_token_stream(), &mut self.excluded);
+            return;
+        }
+        let enabled = self.enabled;
+        let attrs = node.get_attributes().unwrap_or_default();
+        self.enabled = scoped_option(attrs, "rename_items").unwrap_or(enabled);
+        let exported = node.get_attributes().is_some_and(is_exported);
+        // nested items do not see the bindings and `self` of the enclosing function
+        let receivers = take(&mut self.receivers);
+        let self_ty = match node {
+            Item::Impl(item) => type_name(&item.self_ty, None),
+            _ => None,
+        };
+        let self_ty = replace(&mut self.self_ty, self_ty);
+        if let Item::Enum(_) | Item::Struct(_) | Item::Union(_) = node {
+            self.types.extend(item_ident(node).map(Ident::to_string));
+        }
+        match node {
+            Item::Const(item) => self.add(&item.ident, is_private(&item.vis)),
+            Item::Enum(item) => {
+                let private = is_private(&item.vis) && !has_custom_derive(&item.attrs);
+                self.add(&item.ident, private);
+                for variant in &item.variants {
+                    self.defined.insert(variant.ident.to_string());
+                    if !private {
+                        self.excluded.insert(variant.ident.to_string());
+                        self.add_fields(&variant.fields, false);
+                    }
+                }
+            }
+            Item::ExternCrate(item) => {
+                self.excluded.insert(item.ident.to_string());
+                if let Some((_, rename)) = &item.rename {
+                    self.excluded.insert(rename.to_string());
+                }
+            }
+            Item::Fn(item) => self.add(&item.sig.ident, is_private(&item.vis) && !exported),
+            Item::ForeignMod(item) => {
+                for item in &item.items {
+                    match item {
+                        ForeignItem::Fn(item) => self.add(&item.sig.ident, false),
+                        ForeignItem::Static(item) => self.add(&item.ident, false),
+                        ForeignItem::Type(item) => self.add(&item.ident, false),
+                        _ => {}
+                    }
+                }
+            }
+            Item::Impl(item) => {
+                let names = item.items.iter().filter_map(|item| match item {
+                    ImplItem::Const(item) => Some((&item.ident, is_private(&item.vis))),
+                    ImplItem::Fn(item) => Some((&item.sig.ident, is_private(&item.vis))),
+                    ImplItem::Type(item) => Some((&item.ident, is_private(&item.vis))),
+                    _ => None,
+                });
+                match &item.trait_ {
+                    // the items of local traits are added with the trait
+                    Some((_, path, _)) => {
+                        let trait_name = path.segments.last().map(|s| s.ident.to_string());
+                        let names = names.map(|(ident, _)| ident.to_string()).collect();
+                        self.trait_impls
+                            .push((trait_name.unwrap_or_default(), names));
+                    }
+                    None => {
+                        for (ident, private) in names.collect::<Vec<_>>() {
+                            self.add(ident, private);
+                        }
+                    }
+                }
+            }
+            Item::Mod(item) => {
+                self.excluded.insert(item.ident.to_string());
+            }
+            Item::Static(item) => self.add(&item.ident, is_private(&item.vis) && !exported),
+            Item::Struct(item) => self.add_type(&item.ident, &item.vis, &item.attrs, &item.fields),
+            Item::Trait(item) => {
+                let private = is_private(&item.vis);
+                self.add(&item.ident, private);
+                self.traits.insert(item.ident.to_string());
+                for item in &item.items {
+                    match item {
+                        TraitItem::Const(item) => self.add(&item.ident, private),
+                        TraitItem::Fn(item) => self.add(&item.sig.ident, private),
+                        TraitItem::Type(item) => self.add(&item.ident, private),
+                        _ => {}
+                    }
+                }
+            }
+            Item::TraitAlias(item) => self.add(&item.ident, is_private(&item.vis)),
+            Item::Type(item) => self.add(&item.ident, is_private(&item.vis)),
+            Item::Union(item) => {
+                let fields = Fields::Named(item.fields.clone());
+                self.add_type(&item.ident, &item.vis, &item.attrs, &fields);
+            }
+            Item::Use(item) => {
+                let mut paths = vec![];
+                use_tree_paths(&item.tree, &mut vec![], &mut paths);
+                if item.leading_colon.is_some() {
+                    paths
+                        .iter()
+                        .for_each(|path| self.excluded.extend(path.clone()));
+                }
+                self.paths.extend(paths);
+            }
+            _ => {}
+        }
+        if let Some(ident) = item_ident(node) {
+            self.defined.insert(ident.to_string());
+        }
+        visit::visit_item(self, node);
+        self.enabled = enabled;
+        self.receivers = receivers;
+        self.self_ty = self_ty;
+    }
+
+    fn visit_item_fn(&mut self, node: &'ast ItemFn) {
+        for attr in &node.attrs {
+            self.visit_attribute(attr);
+        }
+        self.visit_visibility(&node.vis);
+        self.visit_fn(&node.sig, Some(&node.block));
+    }
+
+    fn visit_impl_item_fn(&mut self, node: &'ast ImplItemFn) {
+        for attr in &node.attrs {
+            self.visit_attribute(attr);
+        }
+        self.visit_visibility(&node.vis);
+        self.visit_fn(&node.sig, Some(&node.block));
+    }
+
+    fn visit_trait_item_fn(&mut self, node: &'ast TraitItemFn) {
+        for attr in &node.attrs {
+            self.visit_attribute(attr);
+        }
+        self.visit_fn(&node.sig, node.default.as_ref());
+    }
+
+    fn visit_expr_field(&mut self, node: &'ast ExprField) {
+        if let Member::Named(ident) = &node.member {
+            let ty = self.receiver_type(&node.base);
+            self.members.push((ident.to_string(), ty));
+        }
+        visit::visit_expr_field(self, node);
+    }
+
+    fn visit_expr_method_call(&mut self, node: &'ast ExprMethodCall) {
+        let ty = self.receiver_type(&node.receiver);
+        self.members.push((node.method.to_string(), ty));
+        visit::visit_expr_method_call(self, node);
+    }
+
+    fn visit_expr_struct(&mut self, node: &'ast ExprStruct) {
+        let ty = struct_path_type(&node.path, self.self_ty.as_ref());
+        for field in &node.fields {
+            if let Member::Named(ident) = &field.member {
+                self.members.push((ident.to_string(), ty.clone()));
+            }
+        }
+        visit::visit_expr_struct(self, node);
+    }
+
+    fn visit_pat_struct(&mut self, node: &'ast PatStruct) {
+        let ty = struct_path_type(&node.path, self.self_ty.as_ref());
+        for field in &node.fields {
+            if let Member::Named(ident) = &field.member {
+                self.members.push((ident.to_string(), ty.clone()));
+            }
+        }
+        visit::visit_pat_struct(self, node);
+    }
+
+    fn visit_ident(&mut self, node: &'ast Ident) {
+        if !self.enabled {
+            self.excluded.insert(node.to_string());
+        }
+    }
+
+    fn visit_path(&mut self, node: &'ast Path) {
+        let segments = node.segments.iter().map(|s| s.ident.to_string());
+        if node.leading_colon.is_some() {
+            self.excluded.extend(segments);
+        } else if node.segments.len() > 1 {
+            self.paths.push(segments.collect());
+        }
+        visit::visit_path(self, node);
+    }
+
+    fn visit_macro(&mut self, node: &'ast Macro) {
+        collect_token_idents(node.to_token_stream(), &mut self.excluded);
+    }
+
+    fn visit_attribute(&mut self, node: &'ast Attribute) {
+        if let Meta::List(list) = &node.meta {
+            collect_token_idents(list.tokens.clone(), &mut self.excluded);
+        }
+        visit::visit_attribute(self, node);
+    }
+}
+
+struct ItemRenamer<'a> {
+    renames: &'a FxHashMap<String, String>,
+}
+
+impl VisitMut for ItemRenamer<'_> {
+    fn visit_item_mut(&mut self, node: &mut Item) {
+        if matches!(node, Item::Verbatim(_)) || node.get_attributes().is_some_and(is_minify_skip) {
+            return;
+        }
+        visit_mut::visit_item_mut(self, node);
+    }
+
+    fn visit_ident_mut(&mut self, node: &mut Ident) {
+        if let Some(name) = self.renames.get(&node.to_string()) {
+            *node = Ident::new(name, node.span());
+        }
+    }
+
+    fn visit_lifetime_mut(&mut self, _: &mut Lifetime) {}
+
+    fn visit_macro_mut(&mut self, _: &mut Macro) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use syn::parse_file;
+    use test_case::test_case;
+
+    #[test]
+    fn test_short_names() {
+        let names: Vec<_> = ShortNames::new(ShortNames::LOWER, ShortNames::ALNUM)
+            .skip(24)
+            .take(5)
+            .collect();
+        assert_eq!(names, vec!["y", "z", "aa", "ab", "ac"]);
+        let names = ShortNames::new(ShortNames::LOWER, ShortNames::ALNUM);
+        assert!(!names.take(2000).any(|name| name == "as" || name == "fn"));
+    }
+
+    #[test_case(
+        indoc!(r#"
+            fn total(values: Vec<usize>) -> usize {
+                let mut accumulated_total = 0;
+                for value in values {
+                    accumulated_total += value;
+                }
+                accumulated_total
+            }
+        "#),
+        "fn total (c : Vec < usize >) -> usize { let mut a = 0 ; for b in c { a += b ; } a }";
+        "frequency"
+    )]
+    #[test_case(
+        indoc!(r#"
+            fn f(x: u32) -> u32 {
+                let x = x + 1;
+                let y = |x: u32| x * 2;
+                { let x = y(x); x }
+            }
+        "#),
+        "fn f (a : u32) -> u32 { let a = a + 1 ; let b = | a : u32 | a * 2 ; { let a = b (a) ; a } }";
+        "shadowing"
+    )]
+    #[test_case(
+        indoc!(r#"
+            struct P { x: i32, y: i32 }
+            const K: i32 = 0;
+            fn f(p: P, count: i32) -> P {
+                let P { x, y: yy } = p;
+                match x { K => {} _none => {} }
+                println!("{}", count);
+                P { x, y: yy }
+            }
+        "#),
+        "struct P { x : i32 , y : i32 } const K : i32 = 0 ; fn f (b : P , count : i32) -> P { let P { x : a , y : c } = b ; match a { K => { } _none => { } } println ! (\"{}\" , count) ; P { x : a , y : c } }";
+        "shorthand, items and macros"
+    )]
+    #[test_case(
+        indoc!(r#"
+            fn f(a: u8, long: u8) -> u8 {
+                fn g(long: u8) -> u8 { long }
+                g(long) + a
+            }
+        "#),
+        "fn f (b : u8 , c : u8) -> u8 { fn g (a : u8) -> u8 { a } g (c) + b }";
+        "nested items"
+    )]
+    fn test_rename_locals(content: &str, expected: &str) {
+        let mut file = parse_file(content).unwrap();
+        rename_locals(&mut file, true, &FxHashSet::default());
+        assert_eq!(file.to_token_stream().to_string(), expected);
+    }
+
+    #[test_case(
+        indoc!(r#"
+            struct Counter { count: u32, pub limit: u32 }
+            impl Counter {
+                fn increment(&mut self) { self.count += 1; }
+                pub fn get(&self) -> u32 { self.count }
+            }
+            impl Default for Counter {
+                fn default() -> Self { Counter { count: 0, limit: 0 } }
+            }
+            fn main() {
+                let mut counter = Counter::default();
+                counter.increment();
+                print(counter.get());
+            }
+            fn print(value: u32) { println!("{}", value); }
+        "#),
+        "struct A { a : u32 , pub limit : u32 } impl A { fn increment (& mut self) { self . a += 1 ; } pub fn get (& self) -> u32 { self . a } } impl Default for A { fn default () -> Self { A { a : 0 , limit : 0 } } } fn main () { let mut counter = A :: default () ; counter . increment () ; b (counter . get ()) ; } fn b (value : u32) { println ! (\"{}\" , value) ; }";
+        "items, fields and methods"
+    )]
+    #[test_case(
+        indoc!(r#"
+            struct Point { x_coord: i32 }
+            impl Point {
+                fn shift(&mut self) { self.x_coord += 1; }
+                fn find(&self) -> bool { false }
+            }
+            fn search(p: &mut Point, v: Vec<i32>) -> Option<&i32> {
+                let q = Point { x_coord: 0 };
+                p.shift();
+                q.find();
+                v.iter().find(|x| **x == p.x_coord)
+            }
+        "#),
+        "struct A { a : i32 } impl A { fn b (& mut self) { self . a += 1 ; } fn find (& self) -> bool { false } } fn c (p : & mut A , v : Vec < i32 >) -> Option < & i32 > { let q = A { a : 0 } ; p . b () ; q . find () ; v . iter () . find (| x | * * x == p . a) }";
+        "receivers"
+    )]
+    #[test_case(
+        "#[derive(Debug, Clone)] struct Point { x_coord: i32 } #[derive(Clone)] struct Size { width: i32 }",
+        "# [derive (Debug , Clone)] struct Point { x_coord : i32 } # [derive (Clone)] struct A { a : i32 }";
+        "debug"
+    )]
+    #[test_case(
+        indoc!(r#"
+            pub struct Public { pub field: u8 }
+            trait Local { fn method(&self); }
+            impl Local for u8 { fn method(&self) {} }
+            #[no_mangle] fn exported() {}
+            extern "C" { fn external(); }
+            fn in_macro() {}
+            fn caller() { in_macro!(); std::mem::drop(()); }
+            fn drop() {}
+            #[rust_minify::skip] fn skipped() { caller() }
+            #[derive(serde::Serialize)] struct Serialized { name: u8 }
+        "#),
+        "pub struct Public { pub field : u8 } trait A { fn a (& self) ; } impl A for u8 { fn a (& self) { } } # [no_mangle] fn exported () { } extern \"C\" { fn external () ; } fn in_macro () { } fn caller () { in_macro ! () ; std :: mem :: drop (()) ; } fn drop () { } # [rust_minify :: skip] fn skipped () { caller () } # [derive (serde :: Serialize)] struct Serialized { name : u8 }";
+        "exclusions"
+    )]
+    fn test_rename_items(content: &str, expected: &str) {
+        let mut file = parse_file(content).unwrap();
+        rename_items(&mut file, true, &FxHashSet::default());
+        assert_eq!(file.to_token_stream().to_string(), expected);
+    }
+}
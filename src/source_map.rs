@@ -0,0 +1,370 @@
+use crate::marker::{LineColumn, LinedSource};
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// A mapping from a position of the minified output to a position of the original source.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Mapping {
+    pub generated: LineColumn,
+    pub original: LineColumn,
+}
+
+/// Mappings from the minified output back to the original source.
+///
+/// Lines are 1-indexed and columns are 0-indexed in UTF-16 code units as in the Source Map v3
+/// specification, which are characters unless a line has characters outside the Basic
+/// Multilingual Plane, such as emoji.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    /// Sorted by `generated`.
+    mappings: Vec<Mapping>,
+}
+
+impl SourceMap {
+    /// Creates a source map from the byte offsets in `output` of the emitted tokens and their
+    /// positions in `source`.
+    pub fn new(output: &str, mut offsets: Vec<(usize, LineColumn)>, source: &str) -> Self {
+        let source = LinedSource::new(source);
+        for (_, original) in &mut offsets {
+            original.column = source.utf16_column(original).unwrap_or(original.column);
+        }
+        offsets.sort_by_key(|(offset, _)| *offset);
+        let mut mappings = Vec::with_capacity(offsets.len());
+        let mut offsets = offsets.into_iter().peekable();
+        let mut generated = LineColumn::new(1, 0);
+        for (i, c) in output.char_indices() {
+            while let Some((_, original)) = offsets.next_if(|(offset, _)| *offset <= i) {
+                mappings.push(Mapping {
+                    generated,
+                    original,
+                });
+            }
+            if c == '\n' {
+                generated = LineColumn::new(generated.line + 1, 0);
+            } else {
+                generated.column += c.len_utf16();
+            }
+        }
+        mappings.extend(offsets.map(|(_, original)| Mapping {
+            generated,
+            original,
+        }));
+        mappings.dedup_by_key(|mapping| mapping.generated);
+        Self { mappings }
+    }
+
+    pub fn mappings(&self) -> &[Mapping] {
+        &self.mappings
+    }
+
+    /// Returns the original position of the token at `generated` in the output.
+    pub fn lookup(&self, generated: LineColumn) -> Option<LineColumn> {
+        let index = self.mappings.partition_point(|m| m.generated <= generated);
+        let mapping = self.mappings[..index].last()?;
+        (mapping.generated.line == generated.line).then_some(mapping.original)
+    }
+
+    /// Serializes the mappings as a Source Map v3 JSON of the output `file` minified from `source`.
+    pub fn to_json(&self, file: Option<&str>, source: &str) -> String {
+        let file = file.map_or(String::new(), |file| {
+            format!(r#""file":{},"#, json_string(file))
+        });
+        format!(
+            r#"{{"version":3,{}"sources":[{}],"names":[],"mappings":"{}"}}"#,
+            file,
+            json_string(source),
+            self.encode_mappings()
+        )
+    }
+
+    /// Parses the `mappings` of a Source Map v3 JSON with a single source.
+    pub fn from_json(json: &str) -> Option<Self> {
+        Self::decode_mappings(&json_member(json, "mappings")?)
+    }
+
+    fn encode_mappings(&self) -> String {
+        let mut buf = String::new();
+        let mut line = 1;
+        let mut prev_column = 0;
+        let mut prev_original = LineColumn::new(1, 0);
+        for (i, mapping) in self.mappings.iter().enumerate() {
+            if mapping.generated.line != line {
+                for _ in line..mapping.generated.line {
+                    buf.push(';');
+                }
+                line = mapping.generated.line;
+                prev_column = 0;
+            } else if i != 0 {
+                buf.push(',');
+            }
+            let original = mapping.original;
+            encode_vlq(
+                &mut buf,
+                mapping.generated.column as i64 - prev_column as i64,
+            );
+            encode_vlq(&mut buf, 0);
+            encode_vlq(&mut buf, original.line as i64 - prev_original.line as i64);
+            encode_vlq(
+                &mut buf,
+                original.column as i64 - prev_original.column as i64,
+            );
+            prev_column = mapping.generated.column;
+            prev_original = original;
+        }
+        buf
+    }
+
+    fn decode_mappings(mappings: &str) -> Option<Self> {
+        let mut decoded = vec![];
+        let mut original = (1i64, 0i64);
+        for (i, line) in mappings.split(';').enumerate() {
+            let mut column = 0i64;
+            for segment in line.split(',').filter(|segment| !segment.is_empty()) {
+                let fields = decode_vlq(segment)?;
+                column += fields[0];
+                if fields.len() < 4 {
+                    continue;
+                }
+                original.0 += fields[2];
+                original.1 += fields[3];
+                decoded.push(Mapping {
+                    generated: LineColumn::new(i + 1, column.try_into().ok()?),
+                    original: LineColumn::new(
+                        original.0.try_into().ok()?,
+                        original.1.try_into().ok()?,
+                    ),
+                });
+            }
+        }
+        Some(Self { mappings: decoded })
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut buf = String::with_capacity(s.len() + 2);
+    buf.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            c if c.is_control() => buf.push_str(&format!("\\u{:04x}", c as u32)),
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+    buf
+}
+
+/// Returns the string value of the member `key` of the JSON object `json`.
+fn json_member(json: &str, key: &str) -> Option<String> {
+    let mut rest = json.trim_start().strip_prefix('{')?.trim_start();
+    if rest.starts_with('}') {
+        return None;
+    }
+    loop {
+        let (name, after) = parse_json_string(rest)?;
+        let value = after.trim_start().strip_prefix(':')?.trim_start();
+        if name == key {
+            return parse_json_string(value).map(|(value, _)| value);
+        }
+        rest = skip_json_value(value)?
+            .trim_start()
+            .strip_prefix(',')?
+            .trim_start();
+    }
+}
+
+/// Parses the JSON string at the start of `s`, returning it and the rest of `s`.
+fn parse_json_string(s: &str) -> Option<(String, &str)> {
+    let mut chars = s.strip_prefix('"')?.char_indices();
+    let mut buf = String::new();
+    let mut surrogate = None;
+    while let Some((i, c)) = chars.next() {
+        match c {
+            _ if surrogate.is_some() && c != '\\' => return None,
+            '"' => return Some((buf, &s[i + 2..])),
+            '\\' => {
+                let c = match chars.next()?.1 {
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'u' => {
+                        let hex: String = (0..4)
+                            .map(|_| chars.next().map(|(_, c)| c))
+                            .collect::<Option<_>>()?;
+                        let unit = u32::from_str_radix(&hex, 16).ok()?;
+                        match (surrogate.take(), unit) {
+                            (None, 0xd800..=0xdbff) => {
+                                surrogate = Some(unit);
+                                continue;
+                            }
+                            (Some(high), 0xdc00..=0xdfff) => {
+                                char::from_u32(0x10000 + ((high - 0xd800) << 10) + (unit - 0xdc00))?
+                            }
+                            (None, _) => char::from_u32(unit)?,
+                            (Some(_), _) => return None,
+                        }
+                    }
+                    c @ ('"' | '\\' | '/') if surrogate.is_none() => c,
+                    _ => return None,
+                };
+                buf.push(c);
+            }
+            c => buf.push(c),
+        }
+    }
+    None
+}
+
+/// Skips the JSON value at the start of `s`, returning the rest of `s`.
+fn skip_json_value(s: &str) -> Option<&str> {
+    match s.chars().next()? {
+        '"' => parse_json_string(s).map(|(_, rest)| rest),
+        '{' | '[' => {
+            let mut depth = 0usize;
+            let mut rest = s;
+            loop {
+                let c = rest.chars().next()?;
+                match c {
+                    '"' => {
+                        rest = parse_json_string(rest)?.1;
+                        continue;
+                    }
+                    '{' | '[' => depth += 1,
+                    '}' | ']' => depth -= 1,
+                    _ => {}
+                }
+                rest = &rest[c.len_utf8()..];
+                if depth == 0 {
+                    return Some(rest);
+                }
+            }
+        }
+        _ => Some(
+            s.trim_start_matches(|c: char| !matches!(c, ',' | '}' | ']') && !c.is_whitespace()),
+        ),
+    }
+}
+
+fn encode_vlq(buf: &mut String, value: i64) {
+    let mut vlq = if value < 0 {
+        ((-value) << 1) | 1
+    } else {
+        value << 1
+    };
+    loop {
+        let mut digit = vlq & 0b11111;
+        vlq >>= 5;
+        if vlq > 0 {
+            digit |= 0b100000;
+        }
+        buf.push(BASE64[digit as usize] as char);
+        if vlq == 0 {
+            break;
+        }
+    }
+}
+
+fn decode_vlq(segment: &str) -> Option<Vec<i64>> {
+    let mut values = vec![];
+    let (mut value, mut shift) = (0i64, 0);
+    for c in segment.bytes() {
+        let digit = BASE64.iter().position(|&b| b == c)? as i64;
+        value += (digit & 0b11111) << shift;
+        if digit & 0b100000 != 0 {
+            shift += 5;
+            continue;
+        }
+        values.push(if value & 1 == 1 {
+            -(value >> 1)
+        } else {
+            value >> 1
+        });
+        (value, shift) = (0, 0);
+    }
+    Some(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case(0, "A")]
+    #[test_case(1, "C")]
+    #[test_case(-1, "D")]
+    #[test_case(15, "e")]
+    #[test_case(16, "gB")]
+    #[test_case(-1000, "x+B")]
+    fn test_vlq(value: i64, expected: &str) {
+        let mut buf = String::new();
+        encode_vlq(&mut buf, value);
+        assert_eq!(buf, expected);
+        assert_eq!(decode_vlq(&buf), Some(vec![value]));
+    }
+
+    #[test]
+    fn test_source_map() {
+        let output = "fn f(){}\nfn g(){}";
+        let offsets = vec![
+            (0, LineColumn::new(1, 0)),
+            (3, LineColumn::new(1, 3)),
+            (9, LineColumn::new(3, 0)),
+            (12, LineColumn::new(3, 4)),
+        ];
+        let map = SourceMap::new(output, offsets, "fn f(){}\n\nfn g() {}");
+        assert_eq!(
+            map.lookup(LineColumn::new(1, 4)),
+            Some(LineColumn::new(1, 3))
+        );
+        assert_eq!(
+            map.lookup(LineColumn::new(2, 0)),
+            Some(LineColumn::new(3, 0))
+        );
+        assert_eq!(
+            map.lookup(LineColumn::new(2, 7)),
+            Some(LineColumn::new(3, 4))
+        );
+        assert_eq!(map.lookup(LineColumn::new(3, 0)), None);
+
+        let json = map.to_json(Some("out.rs"), "src/main.rs");
+        assert_eq!(
+            json,
+            r#"{"version":3,"file":"out.rs","sources":["src/main.rs"],"names":[],"mappings":"AAAA,GAAG;AAEH,GAAI"}"#
+        );
+        assert_eq!(SourceMap::from_json(&json), Some(map));
+    }
+
+    #[test]
+    fn test_source_map_utf16() {
+        let map = SourceMap::new(
+            "g(\"😀\",x)",
+            vec![(0, LineColumn::new(1, 0)), (9, LineColumn::new(1, 7))],
+            "g(\"😀\", x)",
+        );
+        assert_eq!(
+            map.mappings()[1],
+            Mapping {
+                generated: LineColumn::new(1, 7),
+                original: LineColumn::new(1, 8),
+            }
+        );
+        assert_eq!(
+            map.to_json(None, "a.rs"),
+            r#"{"version":3,"sources":["a.rs"],"names":[],"mappings":"AAAA,OAAQ"}"#
+        );
+        assert_eq!(SourceMap::from_json(&map.to_json(None, "a.rs")), Some(map));
+    }
+
+    #[test_case(r#"{"version":3,"sources":["a\"mappings\":\"A.rs"],"names":["mappings"],"mappings":"AAAA"}"#, Some("AAAA"); "key in strings")]
+    #[test_case(r#"{ "x": {"mappings": "B", "y": [1, "]"]}, "mappings" : "A\u0041\/" }"#, Some("AA/"); "nested and escaped")]
+    #[test_case(r#"{"mappings":"\ud83d\ude00"}"#, Some("\u{1f600}"); "surrogate pair")]
+    #[test_case(r#"{"mappings":"\ud83dA"}"#, None; "lone surrogate")]
+    #[test_case(r#"{"version":3,"sources":[]}"#, None; "missing")]
+    #[test_case(r#"["mappings","AAAA"]"#, None; "not an object")]
+    fn test_json_member(json: &str, expected: Option<&str>) {
+        assert_eq!(json_member(json, "mappings").as_deref(), expected);
+    }
+}